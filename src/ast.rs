@@ -0,0 +1,127 @@
+use crate::{
+    tokenizer::Span,
+    parser::op::Op,
+    call::Param,
+    object::{
+        Type,
+        Object,
+        StructDef
+    }
+};
+
+use num_bigint::BigInt;
+
+use std::rc::Rc;
+
+/// A parsed Sputter expression along with the source code it came from
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    None,
+    Name(String),
+    /// `module.name`, which looks up `name` in the module `module` evaluates to
+    Member(Box<Expr>, String),
+    List(Vec<Expr>),
+    /// `{key value...}`
+    Map(Vec<(Expr, Expr)>),
+    /// `(head args... :name value...)`, which calls `head` if it evaluates to a
+    /// function, passing the named values as keyword arguments
+    Call(Box<Expr>, Vec<Expr>, Vec<(String, Expr)>),
+    Op(Op, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `(do exprs...)`, which evaluates each expression in order and returns the last one
+    Do(Vec<Expr>),
+    /// `(match value (pattern body)...)`, which evaluates the body of the first pattern `value` matches
+    Match(Box<Expr>, Vec<MatchArm>),
+    Let(LetKind, Vec<Binding>, Box<Expr>),
+    /// `(def (name params...) -> type body)`, where the return type is optional
+    Def(String, Vec<Param>, Type, Rc<Expr>),
+    Lambda(Vec<Param>, Type, Rc<Expr>),
+    Use(UseDecl),
+    /// `(struct Name fields...)`, which defines a constructor named after the type
+    Struct(Rc<StructDef>),
+    /// `(enum Name variants...)`, which defines each variant like a `struct`
+    Enum(Vec<Rc<StructDef>>)
+}
+
+/// A `(use module)` or `(use module (names...))` expression, where `module`
+/// is a path string or a bare name
+#[derive(Clone, Debug)]
+pub struct UseDecl {
+    pub path: String,
+    /// Names to import directly, or `None` to bind the module itself
+    pub imports: Option<Vec<String>>
+}
+
+/// Which bindings of a `let` expression each value can see
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LetKind {
+    /// `let`, where values only see the names bound outside it
+    Parallel,
+    /// `let*`, where values also see the bindings before them
+    Sequential,
+    /// `letrec`, where every binding is a function that can call any of them
+    Recursive
+}
+
+impl LetKind {
+    /// The keyword that starts the expression
+    pub fn keyword(self) -> &'static str {
+        match self {
+            LetKind::Parallel => "let",
+            LetKind::Sequential => "let*",
+            LetKind::Recursive => "letrec"
+        }
+    }
+}
+
+/// A single `(name expr)` or `(pattern expr)` pair in a `let` expression
+#[derive(Debug)]
+pub struct Binding {
+    /// A `Pattern::Bind` for a plain name
+    pub pattern: Pattern,
+    pub val: Expr,
+    pub span: Span
+}
+
+/// A single `(pattern body)` case in a `match` expression
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expr,
+    pub span: Span
+}
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// `_`, which matches anything without binding it
+    Wildcard,
+    /// A lowercase name, which matches anything and binds it
+    Bind(String),
+    /// A literal like `1`, `"a"`, `true` or `none`, which matches equal values
+    Literal(Object),
+    /// `Name` or `(Name fields...)`, which matches records made by the struct or
+    /// variant `Name` and their fields. The name starts out as a `Type::Named`.
+    Record(Type, Vec<Pattern>),
+    /// `[items...]`, or `[items... & rest]` to match lists at least as long and bind the rest
+    List(Vec<Pattern>, Option<Box<Pattern>>)
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            kind,
+            span
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use num_bigint::BigInt;
+
+use crate::{
+    object::{
+        Type,
+        Object
+    },
+    call::Param,
+    error::{
+        ErrorKind,
+        SputterError
+    },
+    parser::output,
+    interpreter::Interpreter
+};
+
+use std::{
+    error::Error,
+    convert::{
+        TryFrom,
+        TryInto
+    },
+    io::{
+        stdin,
+        stdout,
+        Write
+    }
+};
+
+/// Register builtin functions with Sputter prototype syntax
+macro_rules! gen_builtin {
+    ($interp:ident { $(($name:ident $($params:ident: $types:ident)* $(& $rest:ident: $rest_type:ident)?) => $f:ident)* }) => {
+        $($interp.register(
+            stringify!($name),
+            vec![$(Param::new(stringify!($params), Type::$types),)* $(Param::rest(stringify!($rest), Type::$rest_type))?],
+            $f
+        );)*
+    };
+}
+
+pub fn register(interp: &mut Interpreter) {
+    gen_builtin!(interp {
+        (print & content: Any) => print
+        (println & content: Any) => println
+        (readln) => readln
+        (format format_str: Str & objects: Any) => format
+        (exit code: Int) => exit_with
+        (get coll: Any key: Any) => get
+        (len coll: Any) => len
+        (assoc m: Map key: Any value: Any) => assoc
+        (dissoc m: Map key: Any) => dissoc
+        (has m: Map key: Any) => has
+        (keys m: Map) => keys
+        (values m: Map) => values
+        (range start: Int end: Int) => range
+        (to_float n: Int) => to_float
+        (to_bigint n: Int) => to_bigint
+        (to_int n: BigInt) => to_int
+        (floor x: Float) => floor
+        (round x: Float) => round
+        (wrapping_add a: Int b: Int) => wrapping_add
+        (wrapping_sub a: Int b: Int) => wrapping_sub
+        (wrapping_mul a: Int b: Int) => wrapping_mul
+        (saturating_add a: Int b: Int) => saturating_add
+        (saturating_sub a: Int b: Int) => saturating_sub
+        (saturating_mul a: Int b: Int) => saturating_mul
+    });
+}
+
+fn print(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    print!("{}", repr_all(&args[0]));
+    stdout().flush()?;
+
+    return Ok(Object::None);
+}
+
+fn println(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    println!("{}", repr_all(&args[0]));
+
+    return Ok(Object::None);
+}
+
+/// Shows the values in a list of arguments separated by spaces
+fn repr_all(args: &Object) -> String {
+    let mut out = String::new();
+
+    if let Object::List(ls) = args {
+        for (i, obj) in ls.iter().enumerate() {
+            if i > 0 {out.push(' ');}
+            output::repr_object(obj, &mut out);
+        }
+    }
+
+    return out;
+}
+
+fn readln(_: &[Object]) -> Result<Object, Box<dyn Error>> {
+    let mut s = String::new();
+    stdin().read_line(&mut s)?;
+
+    return Ok(Object::Str(s));
+}
+
+/// Replaces each `%` in the format string with the next argument
+fn format(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    let mut out = String::new();
+
+    let (s, objects) = match (&args[0], &args[1]) {
+        (Object::Str(s), Object::List(objects)) => (s, objects),
+        _ => return Err(format!("Expected string, got `{:?}`", args[0]).into())
+    };
+    let holes = s.matches('%').count();
+    if holes != objects.len() {
+        return Err(format!("Format string has {} `%`, but {} values were given", holes, objects.len()).into());
+    }
+
+    let mut objects = objects.iter();
+    for c in s.chars() {
+        if c == '%' {
+            if let Some(obj) = objects.next() {output::repr_object(obj, &mut out);}
+        }
+        else {out.push(c);}
+    }
+
+    return Ok(Object::Str(out));
+}
+
+/// Stops evaluation by raising an error that carries the exit code
+fn exit_with(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match args[0] {
+        Object::Int(i) => {
+            let code = i.try_into().map_err(|_| format!("Exit code {} is out of range for a 32-bit integer", i))?;
+            Err(Box::new(SputterError::new(ErrorKind::Exit(code), format!("Exited with code {}", i))))
+        },
+        _ => Err(format!("Expected int, got `{:?}`", args[0]).into())
+    }
+}
+
+/// Looks up an index in a list or a key in a map
+fn get(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    let ls = match &args[0] {
+        Object::List(ls) => ls,
+        Object::Map(m) => return match m.get(&args[1]) {
+            Some(obj) => Ok(obj.clone()),
+            None => Err(format!("Key `{}` isn't in the map", output::repr_value(&args[1])).into())
+        },
+        _ => return Err(format!("Expected list or map, got `{:?}`", args[0]).into())
+    };
+    let idx = match args[1] {
+        Object::Int(i) => i,
+        _ => return Err(format!("Expected int, got `{:?}`", args[1]).into())
+    };
+
+    // Negative indices count back from the end of the list
+    let pos = if idx < 0 {ls.len().checked_sub(idx.unsigned_abs() as usize)} else {Some(idx as usize)};
+
+    return match pos.and_then(|p| ls.get(p)) {
+        Some(obj) => Ok(obj.clone()),
+        None => Err(format!("Index is {} but the length of {:?} is {}", idx, ls, ls.len()).into())
+    };
+}
+
+fn len(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::List(ls) => Ok(Object::Int(ls.len().try_into()?)),
+        Object::Str(s) => Ok(Object::Int(s.len().try_into()?)),
+        Object::Map(m) => Ok(Object::Int(m.len().try_into()?)),
+        _ => Err(format!("Expected list, string or map, got `{:?}`", args[0]).into())
+    }
+}
+
+fn assoc(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::Map(m) => Ok(Object::Map(m.assoc(args[1].clone(), args[2].clone()))),
+        _ => Err(format!("Expected map, got `{:?}`", args[0]).into())
+    }
+}
+
+fn dissoc(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::Map(m) => Ok(Object::Map(m.dissoc(&args[1]))),
+        _ => Err(format!("Expected map, got `{:?}`", args[0]).into())
+    }
+}
+
+fn has(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::Map(m) => Ok(Object::Bool(m.has(&args[1]))),
+        _ => Err(format!("Expected map, got `{:?}`", args[0]).into())
+    }
+}
+
+fn keys(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::Map(m) => Ok(Object::List(m.iter().map(|(k, _)| k.clone()).collect())),
+        _ => Err(format!("Expected map, got `{:?}`", args[0]).into())
+    }
+}
+
+fn values(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::Map(m) => Ok(Object::List(m.iter().map(|(_, v)| v.clone()).collect())),
+        _ => Err(format!("Expected map, got `{:?}`", args[0]).into())
+    }
+}
+
+fn range(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    let (start, end) = match (&args[0], &args[1]) {
+        (Object::Int(start), Object::Int(end)) => (*start, *end),
+        _ => return Err(format!("Expected ints, got `{:?}` and `{:?}`", args[0], args[1]).into())
+    };
+
+    return Ok(Object::List((start..end).map(Object::Int).collect()));
+}
+
+fn to_float(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match args[0] {
+        Object::Int(i) => Ok(Object::Float(i as f64)),
+        _ => Err(format!("Expected int, got `{:?}`", args[0]).into())
+    }
+}
+
+fn to_bigint(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match args[0] {
+        Object::Int(i) => Ok(Object::from(BigInt::from(i))),
+        _ => Err(format!("Expected int, got `{:?}`", args[0]).into())
+    }
+}
+
+/// Converts a bigint to an int, failing if it doesn't fit in 64 bits
+fn to_int(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match &args[0] {
+        Object::BigInt(n) => match i64::try_from(&**n) {
+            Ok(i) => Ok(Object::Int(i)),
+            Err(_) => Err(format!("{} doesn't fit in an int", n).into())
+        },
+        _ => Err(format!("Expected bigint, got `{:?}`", args[0]).into())
+    }
+}
+
+fn floor(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match args[0] {
+        Object::Float(f) => float_to_int(f.floor()),
+        _ => Err(format!("Expected float, got `{:?}`", args[0]).into())
+    }
+}
+
+/// Rounds to the nearest int, with halfway cases rounded away from 0
+fn round(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    match args[0] {
+        Object::Float(f) => float_to_int(f.round()),
+        _ => Err(format!("Expected float, got `{:?}`", args[0]).into())
+    }
+}
+
+/// Converts a float with no fractional part to an int, failing if it's out of range
+fn float_to_int(f: f64) -> Result<Object, Box<dyn Error>> {
+    // 2^63 is exactly representable, unlike `i64::MAX`. NaN isn't in any range.
+    if !(-9223372036854775808.0..9223372036854775808.0).contains(&f) {
+        return Err(format!("{:?} can't be converted to an int", f).into());
+    }
+
+    return Ok(Object::Int(f as i64));
+}
+
+/// Applies an integer operation that can't fail to two int arguments
+fn int_op(args: &[Object], f: fn(i64, i64) -> i64) -> Result<Object, Box<dyn Error>> {
+    match (&args[0], &args[1]) {
+        (Object::Int(a), Object::Int(b)) => Ok(Object::Int(f(*a, *b))),
+        _ => Err(format!("Expected ints, got `{:?}` and `{:?}`", args[0], args[1]).into())
+    }
+}
+
+fn wrapping_add(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    int_op(args, i64::wrapping_add)
+}
+
+fn wrapping_sub(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    int_op(args, i64::wrapping_sub)
+}
+
+fn wrapping_mul(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    int_op(args, i64::wrapping_mul)
+}
+
+fn saturating_add(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    int_op(args, i64::saturating_add)
+}
+
+fn saturating_sub(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    int_op(args, i64::saturating_sub)
+}
+
+fn saturating_mul(args: &[Object]) -> Result<Object, Box<dyn Error>> {
+    int_op(args, i64::saturating_mul)
+}
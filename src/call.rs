@@ -1,166 +1,327 @@
 use crate::{
     tokenizer::{
         Buffer,
-        get_tok
+        Span
+    },
+    ast::Expr,
+    env::Env,
+    vm::chunk::{
+        Proto,
+        Captures
     },
     object::{
         Type,
         Object
     },
-    parser::{
-        match_expr,
-        output
-    }
+    module::Modules,
+    error::{
+        ErrorKind,
+        SputterError
+    },
+    parser::output,
+    eval::eval_expr
 };
 
 use std::{
     error::Error,
-    collections::HashMap,
-    convert::TryInto,
-    io::{
-        stdin,
-        stdout,
-        Write
+    fmt::{
+        Debug,
+        Formatter
     },
-    process::exit
+    rc::Rc
 };
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, PartialEq)]
 pub struct Param {
     pub name: String,
-    pub arg_type: Type
+    pub arg_type: Type,
+    /// Whether the parameter is written `& name` and collects the remaining
+    /// arguments into a list. Each of them is checked against `arg_type`.
+    pub rest: bool,
+    /// The value passed if the parameter is left out, written `name=value`
+    pub default: Option<Object>
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Arg {
-    pub val: Object,
-    pub arg_type: Type
+impl Param {
+    pub fn new(name: &str, arg_type: Type) -> Param {
+        Param {
+            name: name.to_owned(),
+            arg_type,
+            rest: false,
+            default: None
+        }
+    }
+
+    /// Creates a parameter that collects any remaining arguments, which has to come last
+    pub fn rest(name: &str, arg_type: Type) -> Param {
+        Param {
+            rest: true,
+            ..Param::new(name, arg_type)
+        }
+    }
+}
+
+impl Debug for Param {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        if self.rest {write!(form, "& ")?;}
+        write!(form, "{}: {:?}", self.name, self.arg_type)?;
+
+        if let Some(default) = &self.default {
+            let mut repr = String::new();
+            output::repr_object(default, &mut repr);
+            write!(form, " = {}", repr)?;
+        }
+
+        return Ok(());
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// A function implemented in Rust. Arguments have already been checked
+/// against the function's parameters when it's called.
+pub type NativeFn = Rc<dyn Fn(&[Object]) -> Result<Object, Box<dyn Error>>>;
+
+#[derive(Clone)]
 pub struct Func {
     pub name: String,
-    pub addr: usize,
-    pub params: Vec<Param>
+    pub params: Vec<Param>,
+    /// Type the function's result is checked against, or `Type::Any` if it wasn't annotated
+    pub ret_type: Type,
+    pub kind: FuncKind
 }
 
-pub struct CallInfo {
-    pub old_addr: usize,
-    pub params: Vec<Param>,
-    pub args: Vec<Arg>
+#[derive(Clone)]
+pub enum FuncKind {
+    Native(NativeFn),
+    /// A function body evaluated by the tree walker, along with the environment it was defined in
+    Closure(Rc<Expr>, Env),
+    /// A function compiled for the VM, along with the values it captured
+    Compiled(Rc<Proto>, Captures)
 }
 
-pub fn call_function<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>, func: &Func, args: &Vec<Arg>) -> Result<Object, Box<dyn Error>> {
-    Ok(match func.name.as_str() {
-        "print" => {
-            let mut out = String::new();
+impl Func {
+    /// Index of the rest parameter, if the function has one
+    pub fn rest_index(&self) -> Option<usize> {
+        self.params.iter().position(|p| p.rest)
+    }
 
-            output::repr_object(&args[0].val, &mut out);
-            print!("{}", out);
-            stdout().flush()?;
+    /// The parameter each argument is passed to in order, with the rest parameter repeated
+    pub fn arg_params(&self) -> impl Iterator<Item = &Param> {
+        let rest = self.params.last().filter(|p| p.rest);
 
-            Object::None
-        },
-        "println" => {
-            let mut out = String::new();
+        self.params.iter().filter(|p| !p.rest).chain(rest.into_iter().flat_map(std::iter::repeat))
+    }
 
-            output::repr_object(&args[0].val, &mut out);
-            println!("{}", out);
+    /// The number of parameters that have to be passed, followed by the number
+    /// that can be passed without a rest parameter
+    fn param_counts(&self) -> (usize, usize) {
+        let fixed = self.params.iter().filter(|p| !p.rest).count();
+        let required = self.params.iter().filter(|p| !p.rest && p.default.is_none()).count();
 
-            Object::None
-        },
-        "readln" => {
-            let mut s = String::new();
-            stdin().read_line(&mut s)?;
+        return (required, fixed);
+    }
+
+    /// Checks whether the function can be called with arguments of the types in
+    /// `params` and returns values of type `ret`
+    pub fn has_signature(&self, params: &[Type], ret: &Type) -> bool {
+        let (required, fixed) = self.param_counts();
+        let argc_fits = params.len() >= required && (self.rest_index().is_some() || params.len() <= fixed);
+
+        return argc_fits
+            && self.arg_params().zip(params).all(|(p, t)| t.fits(&p.arg_type))
+            && self.ret_type.fits(ret);
+    }
 
-            Object::Str(s)
+    /// The parameter a keyword argument called `name` is passed to
+    pub fn keyword_param(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| !p.rest && p.name == name)
+    }
+}
+
+impl PartialEq for Func {
+    fn eq(&self, other: &Func) -> bool {
+        self.name == other.name && self.params == other.params && self.ret_type == other.ret_type && match (&self.kind, &other.kind) {
+            (FuncKind::Native(a), FuncKind::Native(b)) => Rc::ptr_eq(a, b),
+            (FuncKind::Closure(a, a_env), FuncKind::Closure(b, b_env)) => Rc::ptr_eq(a, b) && a_env.ptr_eq(b_env),
+            (FuncKind::Compiled(a, a_caps), FuncKind::Compiled(b, b_caps)) => Rc::ptr_eq(a, b) && Rc::ptr_eq(a_caps, b_caps),
+            _ => false
         }
-        "format" => {
-            let mut out = String::new();
-
-            if let Object::Str(s) = &args[0].val {
-                for c in s.chars() {
-                    if c == '%' {
-                        let mut buf = String::new();
-
-                        output::repr_object(&args[1].val, &mut buf);
-                        out.push_str(buf.as_str());
-                    }
-                    else {out.push(c);}
-                }
-            }
-            else {output::error(buf, format!("Expected string, got `{:?}`", args[0]))?}
-            
-            Object::Str(out)
-        },
-        "exit" => {
-            exit(match args[0].val {
-                Object::Int(i) => i.try_into().unwrap(),
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", args[0]))?; 0}
-            })
-        },
-        "get" => {
-            let ls = match &args[0].val {
-                Object::List(ls) => ls.clone(),
-                _ => {output::error(buf, format!("Expected list, got `{:?}`", args[0]))?; vec![]}
-            };
-            let idx = match args[1].val {
-                Object::Int(i) => {
-                    if i < 0 {
-                        ls.len() - (0 - i) as usize
-                    }
-                    else {i as usize}
-                },
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", args[1]))?; 0}
-            };
-
-            if idx >= ls.len() {
-                output::error(buf, format!("Index is {} but the length of {:?} is {}", idx, ls, ls.len()))?;
-            }
-            ls[idx].clone()
-        },
-        "len" => {
-            match &args[0].val {
-                Object::List(ls) => Object::Int(ls.len().try_into()?),
-                Object::Str(s) => Object::Int(s.len().try_into()?),
-                _ => {output::error(buf, format!("Expected list or string, got `{:?}`", args[0]))?; Object::None}
-            }
+    }
+}
+
+impl Eq for Func {}
+
+impl Debug for Func {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(form, "<function {}(", self.name)?;
+        for (i, p) in self.params.iter().enumerate() {
+            if i > 0 {write!(form, ", ")?;}
+            write!(form, "{:?}", p)?;
+        }
+        write!(form, ")")?;
+
+        if self.ret_type != Type::Any {write!(form, " -> {:?}", self.ret_type)?;}
+        write!(form, ">")
+    }
+}
+
+/// A call to a closure that the tree walker is evaluating the body of
+pub struct CallInfo {
+    pub func: Func,
+    /// The expression that made the call
+    pub span: Span,
+    /// The closure's environment with its arguments bound
+    pub env: Env
+}
+
+impl CallInfo {
+    /// The values bound to the function's parameters
+    pub fn args(&self) -> Vec<Object> {
+        self.func.params.iter().filter_map(|p| self.env.get(&p.name).cloned()).collect()
+    }
+}
+
+/// Adds a call that an error is unwinding through to its trace
+pub fn trace_call(err: Box<dyn Error>, buf: &Buffer, name: &str, span: Span, args: Vec<Object>) -> Box<dyn Error> {
+    return match err.downcast::<SputterError>() {
+        Ok(mut e) => {
+            e.push_frame(buf, name, span, args);
+            e
         },
-        "range" => {
-            let start = match args[0].val {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", args[0]))?; 0}
-            };
-            let end = match args[1].val {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", args[1]))?; 0}
-            };
-
-            let mut res = Vec::<Object>::new();
-            for i in start..end {res.push(Object::Int(i));}
-
-            Object::List(res)
+        Err(e) => e
+    };
+}
+
+/// Describes how many arguments `func` takes
+fn arity(func: &Func) -> String {
+    let (required, fixed) = func.param_counts();
+
+    return match func.rest_index() {
+        Some(_) => format!("at least {}", required),
+        None if required < fixed => format!("{} to {}", required, fixed),
+        None => fixed.to_string()
+    };
+}
+
+/// Puts the arguments of a call to `func` in the order of its parameters, filling
+/// in default values and collecting any rest arguments into a list. The last
+/// `keywords.len()` arguments are passed to the parameters named in `keywords`.
+///
+/// Returns `None` if the arguments are already in order, or describes why they
+/// don't fit the parameters.
+pub fn arrange_args(func: &Func, args: &[Object], keywords: &[String]) -> Result<Option<Vec<Object>>, String> {
+    let rest = func.rest_index();
+    let (required, fixed) = func.param_counts();
+    let (positional, named) = args.split_at(args.len() - keywords.len());
+
+    // Parameters with defaults come after the others, so leaving out a required one is an arity error
+    if (rest.is_none() && positional.len() > fixed) || (keywords.is_empty() && positional.len() < required) {
+        return Err(format!("Function `{}` takes {} arguments, got {}", func.name, arity(func), positional.len()));
+    }
+    if keywords.is_empty() && rest.is_none() && positional.len() == fixed {return Ok(None);}
+
+    let mut slots: Vec<Option<Object>> = positional.iter().take(fixed).cloned().map(Some).collect();
+    slots.resize(fixed, None);
+
+    for (name, val) in keywords.iter().zip(named) {
+        match func.params[..fixed].iter().position(|p| p.name == *name) {
+            Some(i) if slots[i].is_some() => return Err(format!("Parameter `{}` of function `{}` is given more than once", name, func.name)),
+            Some(i) => slots[i] = Some(val.clone()),
+            None => return Err(format!("Function `{}` has no parameter `{}`", func.name, name))
+        }
+    }
+
+    let mut arranged = Vec::with_capacity(func.params.len());
+    for (param, slot) in func.params.iter().zip(slots) {
+        match slot.or_else(|| param.default.clone()) {
+            Some(val) => arranged.push(val),
+            None => return Err(format!("Function `{}` is missing argument `{}`", func.name, param.name))
+        }
+    }
+    if rest.is_some() {arranged.push(Object::List(positional.get(fixed..).unwrap_or_default().into()));}
+
+    return Ok(Some(arranged));
+}
+
+/// Describes why `val` can't be passed to `param`, if it can't
+pub fn type_error(func: &Func, param: &Param, val: &Object) -> Option<String> {
+    if param.arg_type.matches(val) {return None;}
+
+    let arg_type = val.describe_type();
+    // Types from different modules can share a name
+    let note = if format!("{:?}", arg_type) == format!("{:?}", param.arg_type) {" from a different definition"} else {""};
+    return Some(format!(
+        "Parameter `{}` of function `{}` expects type `{:?}`, got type `{:?}`{}",
+        param.name,
+        func.name,
+        param.arg_type,
+        arg_type,
+        note
+    ));
+}
+
+/// Arranges the arguments of a call to `func` with `arrange_args`, blaming the caller at `span`
+pub fn check_args(buf: &Buffer, func: &Func, args: &[Object], keywords: &[String], span: Span) -> Result<Option<Vec<Object>>, Box<dyn Error>> {
+    return match arrange_args(func, args, keywords) {
+        Ok(arranged) => Ok(arranged),
+        Err(msg) => {output::error_at(buf, span, ErrorKind::Type, msg)?; Ok(None)}
+    };
+}
+
+/// Checks an argument against the type of its parameter, blaming the caller at `span`
+pub fn check_type(buf: &Buffer, func: &Func, param: &Param, val: &Object, span: Span) -> Result<(), Box<dyn Error>> {
+    if let Some(msg) = type_error(func, param, val) {output::error_at(buf, span, ErrorKind::Type, msg)?}
+
+    return Ok(());
+}
+
+/// Checks evaluated arguments against the types of their parameters, blaming
+/// each one at its span. Keyword arguments come after the positional ones.
+pub fn check_arg_types(buf: &Buffer, func: &Func, args: &[Object], keywords: &[String], spans: &[Span]) -> Result<(), Box<dyn Error>> {
+    let (positional, named) = args.split_at(args.len() - keywords.len());
+    for ((param, arg), span) in func.arg_params().zip(positional).zip(spans) {
+        check_type(buf, func, param, arg, *span)?;
+    }
+    for ((name, arg), span) in keywords.iter().zip(named).zip(&spans[positional.len()..]) {
+        if let Some(param) = func.keyword_param(name) {check_type(buf, func, param, arg, *span)?;}
+    }
+
+    return Ok(());
+}
+
+/// Checks the result of a function against its return type, blaming the function's body at `span`
+pub fn check_return(buf: &Buffer, name: &str, ret_type: &Type, val: &Object, span: Span) -> Result<(), Box<dyn Error>> {
+    if !ret_type.matches(val) {
+        let msg = format!("Function `{}` should return type `{:?}`, but returned type `{:?}`", name, ret_type, val.describe_type());
+        output::error_at(buf, span, ErrorKind::Type, msg)?
+    }
+
+    return Ok(());
+}
+
+/// Binds arguments to their parameters on top of a function's environment
+pub fn bind_args(env: &Env, params: &[Param], args: Vec<Object>) -> Env {
+    let mut env = env.clone();
+    for (param, arg) in params.iter().zip(args) {
+        env = env.bind(param.name.clone(), arg);
+    }
+
+    return env;
+}
+
+/// Calls a function with checked arguments, placing errors from native functions at `span`
+pub fn call_function(buf: &mut Buffer, modules: &mut Modules, func: &Func, args: Vec<Object>, span: Span) -> Result<Object, Box<dyn Error>> {
+    return Ok(match &func.kind {
+        FuncKind::Native(f) => match f(&args) {
+            Ok(obj) => obj,
+            Err(e) => return Err(Box::new(SputterError::locate(e, buf, span)))
         },
-        _ => {
-            call_stack.push(CallInfo {
-                old_addr: buf.index,
-                params: func.params.clone(),
-                args: args.clone()
-            });
-            
-            let mut temp_names = names.clone();
-            for scope in scope_stack.clone() {
-                for item in scope {
-                    temp_names.remove(&item);
-                }
-            }
-        
-            buf.index = func.addr;
-            let tok = get_tok(buf)?;
-            let res = match_expr(buf, &mut temp_names, call_stack, scope_stack, tok)?;
-            
-            buf.index = call_stack.pop().unwrap().old_addr;
+        FuncKind::Closure(body, env) => {
+            let env = bind_args(env, &func.params, args);
+            let res = eval_expr(buf, modules, &env, body)?;
+            check_return(buf, &func.name, &func.ret_type, &res, body.span)?;
+
             res
-        }
-    })
+        },
+        FuncKind::Compiled(..) => {output::error_at(buf, span, ErrorKind::Runtime, format!("Function `{}` was compiled for the VM", func.name))?; Object::None}
+    });
 }
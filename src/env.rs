@@ -0,0 +1,94 @@
+use crate::{
+    object::Object,
+    module::ModuleId
+};
+
+use std::{
+    cell::OnceCell,
+    rc::Rc
+};
+
+struct Binding {
+    name: String,
+    /// Only empty while the functions of a `letrec` are being created
+    val: OnceCell<Object>,
+    parent: Option<Rc<Binding>>
+}
+
+/// An immutable chain of local bindings (parameters and `let` names), along
+/// with the module whose globals are visible beneath them.
+///
+/// Extending an `Env` never modifies it, so closures can share the
+/// environment they were created in by cloning it. The one exception is
+/// `letrec`, which binds names before it has their values.
+#[derive(Clone)]
+pub struct Env {
+    bindings: Option<Rc<Binding>>,
+    pub module: ModuleId
+}
+
+impl Env {
+    /// Creates an environment with no local bindings in `module`
+    pub fn new(module: ModuleId) -> Env {
+        Env {
+            bindings: None,
+            module
+        }
+    }
+
+    /// Returns a new environment with `name` bound to `val` on top of `self`
+    pub fn bind(&self, name: String, val: Object) -> Env {
+        self.push(name, OnceCell::from(val))
+    }
+
+    /// Returns a new environment with `name` bound on top of `self`, but
+    /// without a value until `init` is called
+    pub fn declare(&self, name: String) -> Env {
+        self.push(name, OnceCell::new())
+    }
+
+    fn push(&self, name: String, val: OnceCell<Object>) -> Env {
+        Env {
+            bindings: Some(Rc::new(Binding {
+                name,
+                val,
+                parent: self.bindings.clone()
+            })),
+            module: self.module
+        }
+    }
+
+    /// Gives the innermost binding of `name` a value, if it doesn't already have one
+    pub fn init(&self, name: &str, val: Object) {
+        let mut cur = &self.bindings;
+
+        while let Some(b) = cur {
+            if b.name == name {
+                let _ = b.val.set(val);
+                return;
+            }
+            cur = &b.parent;
+        }
+    }
+
+    /// Looks up the innermost binding of `name`
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        let mut cur = &self.bindings;
+
+        while let Some(b) = cur {
+            if b.name == name {return b.val.get();}
+            cur = &b.parent;
+        }
+
+        return None;
+    }
+
+    /// Checks whether two environments are the same shared chain
+    pub fn ptr_eq(&self, other: &Env) -> bool {
+        self.module == other.module && match (&self.bindings, &other.bindings) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    tokenizer::{
+        Buffer,
+        Span
+    },
+    object::Object,
+    parser::output::repr_value
+};
+
+use std::{
+    error::Error,
+    fmt::{
+        Display,
+        Formatter
+    }
+};
+
+/// Number of calls kept in the trace of an error; outer calls are only counted
+pub const TRACE_LIMIT: usize = 16;
+
+/// Longest argument value shown in a trace before it's cut off
+const ARG_REPR_LIMIT: usize = 40;
+
+/// Broad category of a `SputterError`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Source code that couldn't be parsed
+    Syntax,
+    /// A name that's undefined or already bound
+    Name,
+    /// A value of the wrong type, or a call with the wrong number of arguments
+    Type,
+    /// An operation that failed while running, like dividing by 0
+    Runtime,
+    /// A source file that couldn't be read
+    Io,
+    /// A module that couldn't be imported
+    Import,
+    /// Raised by the `exit` builtin to stop evaluation with an exit code
+    Exit(i32)
+}
+
+/// Position of an error in the source code, resolved when the error is raised
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// Source file the position is in, if it came from a file
+    pub file: Option<String>,
+    pub line: usize,
+    pub col: usize,
+    /// Full text of the line containing the position
+    pub source_line: String
+}
+
+impl Location {
+    /// Resolves the start of `span` to a position in the file it came from
+    pub fn new(buf: &Buffer, span: Span) -> Location {
+        let (file, text, pos) = match buf.source_pos(span.start) {
+            Some((source, pos)) => (source.name.clone(), &source.text[..], pos),
+            None => (None, &[][..], 0)
+        };
+        let line_start = text[..pos].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let line_end = text[pos..].iter().position(|&b| b == b'\n').map_or(text.len(), |i| pos + i);
+
+        return Location {
+            file,
+            line: text[..line_start].iter().filter(|&&b| b == b'\n').count() + 1,
+            col: pos - line_start + 1,
+            source_line: String::from_utf8_lossy(&text[line_start..line_end]).into_owned()
+        };
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match &self.file {
+            Some(file) => write!(form, "{}:{}:{}", file, self.line, self.col),
+            None => write!(form, "line {}, column {}", self.line, self.col)
+        }
+    }
+}
+
+/// A function call that was active when an error was raised
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceFrame {
+    pub name: String,
+    pub args: Vec<Object>,
+    /// The expression that made the call
+    pub span: Span,
+    pub location: Location
+}
+
+/// An error raised while parsing or evaluating Sputter code
+#[derive(Clone, Debug, PartialEq)]
+pub struct SputterError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    pub location: Option<Location>,
+    /// Calls the error unwound through, innermost first
+    pub trace: Vec<TraceFrame>,
+    /// Number of calls left out of `trace` because it was full
+    pub hidden_frames: usize
+}
+
+impl SputterError {
+    /// Creates an error that isn't tied to any source code
+    pub fn new(kind: ErrorKind, message: String) -> SputterError {
+        SputterError {
+            kind,
+            message,
+            span: None,
+            location: None,
+            trace: Vec::new(),
+            hidden_frames: 0
+        }
+    }
+
+    /// Creates an error at the code covered by `span`
+    pub fn at(buf: &Buffer, span: Span, kind: ErrorKind, message: String) -> SputterError {
+        SputterError {
+            span: Some(span),
+            location: Some(Location::new(buf, span)),
+            ..SputterError::new(kind, message)
+        }
+    }
+
+    /// Places an error from a native function at `span`, leaving errors that
+    /// already have a location untouched
+    pub fn locate(err: Box<dyn Error>, buf: &Buffer, span: Span) -> SputterError {
+        return match err.downcast::<SputterError>() {
+            Ok(e) if e.span.is_some() => *e,
+            Ok(e) => SputterError::at(buf, span, e.kind, e.message),
+            Err(e) => SputterError::at(buf, span, ErrorKind::Runtime, e.to_string())
+        };
+    }
+
+    /// Records a call to `name` made at `span` that the error unwound through
+    pub fn push_frame(&mut self, buf: &Buffer, name: &str, span: Span, args: Vec<Object>) {
+        if self.trace.len() == TRACE_LIMIT {
+            self.hidden_frames += 1;
+            return;
+        }
+
+        self.trace.push(TraceFrame {
+            name: name.to_owned(),
+            args,
+            span,
+            location: Location::new(buf, span)
+        });
+    }
+
+    /// Formats the error along with the offending line, optionally in color
+    pub fn render(&self, color: bool) -> String {
+        let (red, reset) = if color {("\u{001b}[31m", "\u{001b}[0m")} else {("", "")};
+
+        let header = match &self.location {
+            Some(loc @ Location {file: Some(_), ..}) => format!("Error at {}", loc),
+            Some(loc) => format!("Error at line {}", loc.line),
+            None => "Error".to_owned()
+        };
+        let mut out = format!("{}{}{}: {}", red, header, reset, self.message);
+
+        if let Some(loc) = &self.location {
+            out.push_str(&format!("\n{}\n{}{:>width$}{}", loc.source_line, red, '^', reset, width = loc.col));
+        }
+
+        for frame in &self.trace {
+            let args: Vec<String> = frame.args.iter().map(repr_arg).collect();
+            out.push_str(&format!("\n  in {}({}) called at {}", frame.name, args.join(", "), frame.location));
+        }
+        if self.hidden_frames > 0 {
+            out.push_str(&format!("\n  ... and {} more calls", self.hidden_frames));
+        }
+
+        return out;
+    }
+}
+
+/// Shows a value the way it's written in source code, cut short if it's long
+fn repr_arg(obj: &Object) -> String {
+    let mut repr = repr_value(obj);
+    if repr.chars().count() > ARG_REPR_LIMIT {
+        repr = repr.chars().take(ARG_REPR_LIMIT).collect();
+        repr.push_str("...");
+    }
+
+    return repr;
+}
+
+impl Display for SputterError {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(form, "{}", self.render(false))
+    }
+}
+
+impl Error for SputterError {}
@@ -0,0 +1,295 @@
+use crate::{
+    tokenizer::{
+        Buffer,
+        Span
+    },
+    ast::{
+        Expr,
+        ExprKind,
+        Binding,
+        LetKind,
+        MatchArm
+    },
+    env::Env,
+    object::{
+        Type,
+        Object
+    },
+    module::{
+        ModuleId,
+        Modules
+    },
+    error::ErrorKind,
+    pattern::{
+        select,
+        destructure
+    },
+    call::{
+        Func,
+        FuncKind,
+        CallInfo,
+        trace_call,
+        call_function,
+        bind_args,
+        check_args,
+        check_arg_types,
+        check_return
+    },
+    parser::{
+        op::apply_op,
+        output
+    }
+};
+
+use std::{
+    error::Error,
+    rc::Rc
+};
+
+/// Evaluates a parsed expression.
+///
+/// Expressions in tail position (the branches of `if`, the body of `let` and the
+/// body of a called function) are evaluated in a loop rather than recursively, so
+/// tail calls run in constant stack space. An error raised inside a called function
+/// gets the call added to its trace; calls replaced by a tail call don't appear.
+pub fn eval_expr(buf: &mut Buffer, modules: &mut Modules, env: &Env, expr: &Expr) -> Result<Object, Box<dyn Error>> {
+    let mut call = None;
+
+    let res = eval_tail(buf, modules, env, expr, &mut call).and_then(|obj| match &call {
+        Some(CallInfo {func: func @ Func {kind: FuncKind::Closure(body, _), ..}, ..}) => {
+            check_return(buf, &func.name, &func.ret_type, &obj, body.span)?;
+            Ok(obj)
+        },
+        _ => Ok(obj)
+    });
+
+    return res.map_err(|e| match call {
+        Some(call) => trace_call(e, buf, &call.func.name, call.span, call.args()),
+        None => e
+    });
+}
+
+/// Evaluates an expression and everything it tail calls, keeping track of the
+/// function whose body is being evaluated in `call`
+fn eval_tail(buf: &mut Buffer, modules: &mut Modules, env: &Env, expr: &Expr, call: &mut Option<CallInfo>) -> Result<Object, Box<dyn Error>> {
+    let mut env = env.clone();
+    let mut expr = expr;
+    // Keeps the body of the function being evaluated alive after a tail call
+    let mut body: Rc<Expr>;
+
+    loop {
+        return Ok(match &expr.kind {
+            ExprKind::Int(i) => Object::Int(*i),
+            ExprKind::Float(f) => Object::Float(*f),
+            ExprKind::BigInt(n) => Object::BigInt(n.clone()),
+            ExprKind::Str(s) => Object::Str(s.clone()),
+            ExprKind::Bool(b) => Object::Bool(*b),
+            ExprKind::None => Object::None,
+            ExprKind::Name(n) => eval_name_expr(buf, modules, &env, n, expr.span)?,
+            ExprKind::Member(module, member) => {
+                let obj = eval_expr(buf, modules, &env, module)?;
+
+                modules.member(buf, &obj, member, expr.span)?
+            },
+            ExprKind::List(items) => {
+                let mut ls = Vec::<Object>::new();
+                for item in items {
+                    ls.push(eval_expr(buf, modules, &env, item)?);
+                }
+
+                Object::List(ls.into())
+            },
+            ExprKind::Map(entries) => {
+                let mut map = Vec::<(Object, Object)>::new();
+                for (key, value) in entries {
+                    map.push((eval_expr(buf, modules, &env, key)?, eval_expr(buf, modules, &env, value)?));
+                }
+
+                Object::Map(map.into_iter().collect())
+            },
+            ExprKind::Call(head, arg_exprs, keywords) => {
+                let head_obj = eval_expr(buf, modules, &env, head)?;
+                let func = match head_obj {
+                    Object::Function(f) => f,
+                    // A parenthesized value with no arguments evaluates to itself
+                    _ if arg_exprs.is_empty() && keywords.is_empty() => return Ok(head_obj),
+                    _ => {output::error_at(buf, head.span, ErrorKind::Type, format!("`{:?}` is not a function", head_obj))?; return Ok(Object::None)}
+                };
+
+                let args = eval_args(buf, modules, &env, &func, arg_exprs, keywords, expr.span)?;
+
+                // A tail call would skip checking the result against the caller's return type
+                let checks_result = match call {
+                    Some(caller) => caller.func.ret_type == Type::Any || caller.func.ret_type == func.ret_type,
+                    None => true
+                };
+
+                match &func.kind {
+                    FuncKind::Closure(func_body, func_env) if checks_result => {
+                        let func_body = func_body.clone();
+                        env = bind_args(func_env, &func.params, args);
+                        *call = Some(CallInfo {
+                            env: env.clone(),
+                            span: expr.span,
+                            func
+                        });
+
+                        body = func_body;
+                        expr = &body;
+                        continue;
+                    },
+                    _ => call_function(buf, modules, &func, args, expr.span)?
+                }
+            },
+            ExprKind::Op(op, lhs, rhs) => {
+                let lhs = eval_expr(buf, modules, &env, lhs)?;
+                let rhs = eval_expr(buf, modules, &env, rhs)?;
+
+                apply_op(buf, expr.span, *op, lhs, rhs)?
+            },
+            ExprKind::If(cond, then, otherwise) => {
+                let t = eval_expr(buf, modules, &env, cond)?;
+                let cond = match t {
+                    Object::Bool(b) => b,
+                    _ => {output::error_at(buf, cond.span, ErrorKind::Type, format!("Conditional expression must have type `bool`, not `{:?}`", t))?; false}
+                };
+
+                expr = if cond {then} else {otherwise};
+                continue;
+            },
+            ExprKind::Do(exprs) => {
+                // Only the last expression is in tail position
+                let (last, init) = exprs.split_last().unwrap();
+                for e in init {eval_expr(buf, modules, &env, e)?;}
+
+                expr = last;
+                continue;
+            },
+            ExprKind::Match(value, arms) => {
+                let (arm, scope) = eval_match(buf, modules, &env, value, arms, expr.span)?;
+                env = scope;
+                expr = &arm.body;
+                continue;
+            },
+            ExprKind::Let(kind, bindings, let_body) => {
+                env = eval_let_bindings(buf, modules, &env, *kind, bindings)?;
+                expr = let_body;
+                continue;
+            },
+            ExprKind::Def(name, params, ret_type, body) => {
+                modules.define(env.module, name.clone(), Object::Function(Func {
+                    name: name.clone(),
+                    params: params.clone(),
+                    ret_type: ret_type.clone(),
+                    kind: FuncKind::Closure(body.clone(), env.clone())
+                }));
+
+                Object::None
+            },
+            ExprKind::Lambda(params, ret_type, body) => Object::Function(Func {
+                name: "lambda".to_owned(),
+                params: params.clone(),
+                ret_type: ret_type.clone(),
+                kind: FuncKind::Closure(body.clone(), env.clone())
+            }),
+            ExprKind::Struct(def) => {
+                modules.define(env.module, def.ty.name.to_string(), def.value());
+
+                Object::None
+            },
+            ExprKind::Enum(variants) => {
+                for def in variants {
+                    modules.define(env.module, def.ty.name.to_string(), def.value());
+                }
+
+                Object::None
+            },
+            ExprKind::Use(decl) => {
+                modules.use_module(buf, env.module, decl, expr.span, eval_top_level)?;
+
+                Object::None
+            }
+        });
+    }
+}
+
+/// Evaluates a top-level expression of a module
+pub fn eval_top_level(buf: &mut Buffer, modules: &mut Modules, module: ModuleId, expr: &Expr) -> Result<Object, Box<dyn Error>> {
+    return eval_expr(buf, modules, &Env::new(module), expr);
+}
+
+fn eval_name_expr(buf: &Buffer, modules: &Modules, env: &Env, name: &str, span: Span) -> Result<Object, Box<dyn Error>> {
+    if let Some(obj) = env.get(name) {
+        return Ok(obj.clone());
+    }
+
+    return Ok(match modules.get(env.module, name) {
+        Some(obj) => obj.clone(),
+        None => {output::error_at(buf, span, ErrorKind::Name, format!("Undefined name: `{}`", name))?; Object::None}
+    });
+}
+
+/// Evaluates the bindings of a `let` expression, returning the scope for its body
+fn eval_let_bindings(buf: &mut Buffer, modules: &mut Modules, env: &Env, kind: LetKind, bindings: &[Binding]) -> Result<Env, Box<dyn Error>> {
+    let mut scope = env.clone();
+
+    if kind == LetKind::Recursive {
+        // Every function is created in a scope where all of their names are bound
+        for binding in bindings {
+            for name in binding.pattern.names() {scope = scope.declare(name.to_owned());}
+        }
+        for binding in bindings {
+            let val = eval_expr(buf, modules, &scope, &binding.val)?;
+            for name in binding.pattern.names() {scope.init(name, val.clone());}
+        }
+
+        return Ok(scope);
+    }
+
+    for binding in bindings {
+        let outer = if kind == LetKind::Sequential {&scope} else {env};
+        let val = eval_expr(buf, modules, outer, &binding.val)?;
+        let values = destructure(buf, &binding.pattern, &val, binding.span)?;
+
+        scope = bind_names(&scope, binding.pattern.names(), values);
+    }
+
+    return Ok(scope);
+}
+
+/// Binds the names of a pattern on top of `env`, shadowing any outer bindings
+fn bind_names(env: &Env, names: Vec<&str>, values: Vec<Object>) -> Env {
+    let mut scope = env.clone();
+    for (name, val) in names.into_iter().zip(values) {
+        scope = scope.bind(name.to_owned(), val);
+    }
+
+    return scope;
+}
+
+/// Picks the arm of a `match` expression that handles its value, returning it
+/// along with the scope for its body
+fn eval_match<'a>(buf: &mut Buffer, modules: &mut Modules, env: &Env, value: &Expr, arms: &'a [MatchArm], span: Span) -> Result<(&'a MatchArm, Env), Box<dyn Error>> {
+    let obj = eval_expr(buf, modules, env, value)?;
+    let (idx, bound) = select(buf, arms.iter().map(|arm| &arm.pattern), &obj, span)?;
+    let arm = &arms[idx];
+    let scope = bind_names(env, arm.pattern.names(), bound);
+
+    return Ok((arm, scope));
+}
+
+/// Evaluates the arguments of a call to `func`, then type-checks them and
+/// returns them in the order of its parameters
+fn eval_args(buf: &mut Buffer, modules: &mut Modules, env: &Env, func: &Func, arg_exprs: &[Expr], keywords: &[(String, Expr)], span: Span) -> Result<Vec<Object>, Box<dyn Error>> {
+    let exprs: Vec<&Expr> = arg_exprs.iter().chain(keywords.iter().map(|(_, arg)| arg)).collect();
+    let mut args = Vec::<Object>::new();
+    for arg_expr in &exprs {
+        args.push(eval_expr(buf, modules, env, arg_expr)?);
+    }
+
+    let names: Vec<String> = keywords.iter().map(|(name, _)| name.clone()).collect();
+    let spans: Vec<Span> = exprs.iter().map(|arg| arg.span).collect();
+    check_arg_types(buf, func, &args, &names, &spans)?;
+
+    return Ok(check_args(buf, func, &args, &names, span)?.unwrap_or(args));
+}
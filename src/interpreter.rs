@@ -0,0 +1,218 @@
+use crate::{
+    tokenizer::Buffer,
+    object::{
+        Type,
+        Object
+    },
+    error::{
+        ErrorKind,
+        SputterError
+    },
+    call::{
+        Func,
+        FuncKind,
+        Param,
+        arrange_args,
+        type_error,
+        check_return,
+        bind_args
+    },
+    builtins,
+    module::{
+        MAIN,
+        Modules
+    },
+    eval::{
+        eval_expr,
+        eval_top_level
+    },
+    vm::{
+        Vm,
+        run_top_level
+    }
+};
+
+use std::{
+    error::Error,
+    env::{
+        var_os,
+        split_paths
+    },
+    fs::read_to_string,
+    path::{
+        Path,
+        PathBuf
+    },
+    rc::Rc
+};
+
+/// Where the standard library is installed, unless overridden by
+/// `$SPUTTER_STDLIB` when Sputter is built
+pub const STDLIB_PATH: &str = match option_env!("SPUTTER_STDLIB") {
+    Some(path) => path,
+    None => "/usr/local/lib/sputter"
+};
+
+/// Strategy used to run parsed expressions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Walk the syntax tree directly
+    Tree,
+    /// Compile to bytecode and run it on a stack VM
+    Vm
+}
+
+/// An embeddable Sputter interpreter holding global definitions between evaluations
+pub struct Interpreter {
+    backend: Backend,
+    buf: Buffer,
+    modules: Modules
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter that uses the tree-walking backend
+    pub fn new() -> Interpreter {
+        Interpreter::with_backend(Backend::Tree)
+    }
+
+    pub fn with_backend(backend: Backend) -> Interpreter {
+        let mut interp = Interpreter {
+            backend,
+            buf: Buffer::new_empty(),
+            modules: Modules::new()
+        };
+
+        builtins::register(&mut interp);
+
+        return interp;
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Adds a directory to search for modules that aren't found next to the file using them.
+    /// Directories are searched in the order they're added.
+    pub fn add_search_path<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.modules.search_path.push(dir.into());
+    }
+
+    /// The directories listed in `$SPUTTER_INCLUDE`, followed by the standard library
+    pub fn default_search_path() -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = match var_os("SPUTTER_INCLUDE") {
+            Some(include) => split_paths(&include).collect(),
+            None => Vec::new()
+        };
+        dirs.push(PathBuf::from(STDLIB_PATH));
+
+        return dirs;
+    }
+
+    /// Evaluates Sputter source code, returning the value of its last expression.
+    ///
+    /// If an error is raised, the rest of `src` is skipped and definitions made
+    /// before the error are kept, so the interpreter can keep being used.
+    pub fn eval_str(&mut self, src: &str) -> Result<Object, Box<dyn Error>> {
+        return self.eval_source(None, src);
+    }
+
+    /// Evaluates a Sputter source file, returning the value of its last expression
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Object, Box<dyn Error>> {
+        let file = path.as_ref().display().to_string();
+        let src = match read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) => return Err(Box::new(SputterError::new(ErrorKind::Io, format!("Couldn't read `{}`: {}", file, e))))
+        };
+
+        return self.eval_source(Some(path.as_ref()), &src);
+    }
+
+    /// Adds source code to the buffer and evaluates it, skipping the rest of it after an error
+    fn eval_source(&mut self, file: Option<&Path>, src: &str) -> Result<Object, Box<dyn Error>> {
+        self.buf.append(file.map(|f| f.display().to_string()).as_deref(), src);
+
+        let eval = match self.backend {
+            Backend::Tree => eval_top_level,
+            Backend::Vm => run_top_level
+        };
+        let res = match file {
+            Some(path) => self.modules.eval_main_file(&mut self.buf, path, eval),
+            None => self.modules.eval_source(&mut self.buf, MAIN, eval)
+        };
+        if res.is_err() {self.buf.index = self.buf.len;}
+
+        return res;
+    }
+
+    /// Defines a global function implemented in Rust.
+    ///
+    /// Calls are checked against `params` before `f` runs, so `f` always receives
+    /// `params.len()` arguments of the declared types. A rest parameter made with
+    /// `Param::rest` receives a list of the remaining arguments. Errors returned by `f` are
+    /// reported at the call site, keeping their kind if they're a `SputterError`.
+    ///
+    /// ```
+    /// use sputter::{Interpreter, Object, Type, call::Param};
+    ///
+    /// let mut interp = Interpreter::new();
+    /// interp.register("square", vec![Param::new("n", Type::Int)], |args| match args[0] {
+    ///     Object::Int(n) => Ok(Object::Int(n * n)),
+    ///     _ => unreachable!()
+    /// });
+    ///
+    /// assert_eq!(interp.eval_str("(square 7)").unwrap(), Object::Int(49));
+    /// ```
+    pub fn register<F>(&mut self, name: &str, params: Vec<Param>, f: F)
+        where F: Fn(&[Object]) -> Result<Object, Box<dyn Error>> + 'static
+    {
+        self.modules.builtins.insert(name.to_owned(), Object::Function(Func {
+            name: name.to_owned(),
+            params,
+            ret_type: Type::Any,
+            kind: FuncKind::Native(Rc::new(f))
+        }));
+    }
+
+    /// Looks up a global name in the main module
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.modules.get(MAIN, name)
+    }
+
+    /// Calls the global function `name` with arguments converted from Rust values
+    pub fn call(&mut self, name: &str, mut args: Vec<Object>) -> Result<Object, Box<dyn Error>> {
+        let func = match self.get(name) {
+            Some(Object::Function(f)) => f.clone(),
+            Some(obj) => return Err(Box::new(SputterError::new(ErrorKind::Type, format!("`{:?}` is not a function", obj)))),
+            None => return Err(Box::new(SputterError::new(ErrorKind::Name, format!("Undefined name: `{}`", name))))
+        };
+
+        for (param, arg) in func.arg_params().zip(&args) {
+            if let Some(msg) = type_error(&func, param, arg) {
+                return Err(Box::new(SputterError::new(ErrorKind::Type, msg)));
+            }
+        }
+        match arrange_args(&func, &args, &[]) {
+            Ok(Some(arranged)) => args = arranged,
+            Ok(None) => (),
+            Err(msg) => return Err(Box::new(SputterError::new(ErrorKind::Type, msg)))
+        }
+
+        return match &func.kind {
+            FuncKind::Native(f) => f(&args),
+            FuncKind::Closure(body, env) => {
+                let env = bind_args(env, &func.params, args);
+                let res = eval_expr(&mut self.buf, &mut self.modules, &env, body)?;
+                check_return(&self.buf, &func.name, &func.ret_type, &res, body.span)?;
+
+                Ok(res)
+            },
+            FuncKind::Compiled(proto, captures) => Vm::new().call(&mut self.buf, &mut self.modules, proto.clone(), captures.clone(), args)
+        };
+    }
+}
@@ -0,0 +1,47 @@
+//! Sputter: a pure-functional language written entirely in Rust.
+//!
+//! The [`Interpreter`] type evaluates Sputter source code and lets Rust code
+//! call into the functions it defines:
+//!
+//! ```
+//! use sputter::{Interpreter, Object};
+//!
+//! let mut interp = Interpreter::new();
+//! interp.eval_str("(def (double n: int) (* n 2))").unwrap();
+//!
+//! let res = interp.call("double", vec![Object::from(21)]).unwrap();
+//! assert_eq!(res, Object::Int(42));
+//! ```
+
+#![allow(clippy::needless_return)]
+
+pub mod tokenizer;
+pub mod ast;
+pub mod env;
+pub mod module;
+pub mod object;
+pub mod error;
+pub mod pattern;
+pub mod resolve;
+pub mod call;
+pub mod parser;
+pub mod eval;
+pub mod vm;
+pub mod builtins;
+pub mod interpreter;
+
+pub use crate::{
+    object::{
+        Type,
+        Object,
+        ConversionError
+    },
+    error::{
+        ErrorKind,
+        SputterError
+    },
+    interpreter::{
+        Backend,
+        Interpreter
+    }
+};
@@ -1,32 +1,17 @@
-mod tokenizer;
-mod object;
-mod call;
-mod parser;
-
-use crate::{
-    tokenizer::{
-        Buffer,
-        get_tok
-    },
-    object::{
-        Type,
-        Object
-    },
-    call::{
-        Func,
-        CallInfo,
-        Param,
-    },
-    parser::match_expr
+#![allow(clippy::needless_return)]
+
+use sputter::{
+    Backend,
+    Interpreter,
+    ErrorKind,
+    SputterError,
+    parser::output
 };
 
 use std::{
-    collections::HashMap,
     error::Error,
     thread::Builder,
-    fs::File,
-    io::Read,
-    path::Path,
+    process::exit,
     io::{
         stdin,
         stdout,
@@ -42,23 +27,6 @@ use clap::{
     crate_description
 };
 
-/// Define builtin functions with Sputter prototype syntax
-macro_rules! gen_builtin {
-    ($names:ident { $(($name:ident $($params:ident: $types:ident)*))*}) => {
-        $($names.insert(
-            stringify!($name).to_owned(),
-            Object::Function(Func {
-                name: stringify!($name).to_owned(),
-                addr: 0,
-                params: vec![$(Param {
-                    name: stringify!($params).to_owned(),
-                    arg_type: Type::$types
-                }),*]
-            })
-        ));*
-    };
-}
-
 /// Shorthand macro for spawning a thread
 macro_rules! spawn_thread {
     ($name:literal, $stack_size:expr, $f:expr) => {
@@ -66,6 +34,16 @@ macro_rules! spawn_thread {
     };
 }
 
+/// Reports an error that stopped a script, returning the exit code for it
+fn exit_code(err: &(dyn Error + 'static), color: bool) -> i32 {
+    if let Some(SputterError {kind: ErrorKind::Exit(code), ..}) = err.downcast_ref::<SputterError>() {
+        return *code;
+    }
+    output::report(err, color);
+
+    return 1;
+}
+
 /// Constant specifying the amount of stack space available to the execution thread
 const DEFAULT_STACK_SIZE: usize = 32 * 1024 * 1024;
 
@@ -86,10 +64,28 @@ fn main() -> Result<(), Box<dyn Error>> {
             .help("Specify stack space for the execution thread (in megabytes)")
             .takes_value(true)
         )
+        .arg(Arg::with_name("BACKEND")
+            .short("b")
+            .long("backend")
+            .value_name("BACKEND")
+            .help("Select how code is executed: `tree` walks the syntax tree, `vm` compiles to bytecode")
+            .possible_values(&["tree", "vm"])
+            .default_value("tree")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("INCLUDE")
+            .short("I")
+            .long("include")
+            .value_name("DIR")
+            .help("Add a directory to search for modules, before `$SPUTTER_INCLUDE` and the standard library")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+        )
         .arg(Arg::with_name("COLOR_OFF")
             .short("o")
             .long("color-off")
-            .help("Don't use color in REPL output")
+            .help("Don't use color in output")
             .takes_value(false)
         )
         .get_matches();
@@ -98,67 +94,54 @@ fn main() -> Result<(), Box<dyn Error>> {
         Some(s) => s.parse::<usize>().unwrap() * 1024 * 1024,
         None => DEFAULT_STACK_SIZE
     }, {
-        let mut names = HashMap::<String, Object>::new();
-        let mut call_stack = Vec::<CallInfo>::new();
-        let mut scope_stack = Vec::<Vec<String>>::new();
-
-        gen_builtin!(names {
-            (print content: Any)
-            (println content: Any)
-            (readln)
-            (format format_str: Str object: Any)
-            (exit code: Int)
-            (get ls: Any idx: Int)
-            (len ls: Any)
-            (range start: Int end: Int)
+        let mut interp = Interpreter::with_backend(match matches.value_of("BACKEND") {
+            Some("vm") => Backend::Vm,
+            _ => Backend::Tree
         });
+        for dir in matches.values_of("INCLUDE").into_iter().flatten() {
+            interp.add_search_path(dir);
+        }
+        for dir in Interpreter::default_search_path() {
+            interp.add_search_path(dir);
+        }
+
+        let no_color = matches.is_present("COLOR_OFF");
 
         // Run file specified by command line arg
         if let Some(filename) = matches.value_of("INPUT") {
-            let mut buf = Buffer::new(read(filename).unwrap()).unwrap();
-
-            while buf.index < buf.len {
-                let tok = get_tok(&mut buf).unwrap();
-                match_expr(&mut buf, &mut names, &mut call_stack, &mut scope_stack, tok).unwrap();
+            match interp.eval_file(filename) {
+                Ok(_) => 0,
+                Err(e) => exit_code(&*e, !no_color)
             }
         }
         // REPL
         else {
             let stdin = stdin();
-            let no_color = matches.is_present("COLOR_OFF");
-            let mut buf = Buffer::new_empty();
 
             loop {
-                stdout().write(b">>> ").unwrap();
+                stdout().write_all(b">>> ").unwrap();
                 stdout().flush().unwrap();
 
-                buf.add_line(&stdin).unwrap();
-
-                let mut res = Object::None;
-                while buf.index < buf.len {
-                    let tok = get_tok(&mut buf).unwrap();
-                    res = match_expr(&mut buf, &mut names, &mut call_stack, &mut scope_stack, tok).unwrap();
+                let mut line = String::new();
+                if stdin.read_line(&mut line).unwrap() == 0 {break 0;}
+
+                match interp.eval_str(&line) {
+                    Ok(res) => {
+                        if !no_color {println!("\u{001b}[36m=> {:?}\u{001b}[0m", res);}
+                        else {println!("=> {:?}", res)}
+                    },
+                    // Only `exit` ends the session; other errors are reported and the REPL carries on
+                    Err(e) => match e.downcast_ref::<SputterError>() {
+                        Some(SputterError {kind: ErrorKind::Exit(code), ..}) => break *code,
+                        _ => output::report(&*e, !no_color)
+                    }
                 }
-                
-                if !no_color {println!("\u{001b}[36m=> {:?}\u{001b}[0m", res);}
-                else {println!("=> {:?}", res)}
             }
         }
     });
 
-    child.join().unwrap();
+    let code = child.join().unwrap();
+    if code != 0 {exit(code);}
 
     return Ok(());
 }
-
-/// Reads the contents of a file to an allocated buffer
-fn read(filename: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-    let path = Path::new(filename);
-    
-    let mut file = File::open(&path)?;
-  
-    let mut v = Vec::<u8>::new();
-    file.read_to_end(&mut v)?;
-    
-    return Ok(v);
-}
@@ -0,0 +1,312 @@
+use crate::{
+    tokenizer::{
+        Buffer,
+        Span
+    },
+    ast::{
+        Expr,
+        UseDecl
+    },
+    object::{
+        Object,
+        RecordType,
+        TypeDef
+    },
+    error::ErrorKind,
+    resolve::resolve_types,
+    parser::{
+        parse_next_expr,
+        output
+    }
+};
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{
+        Debug,
+        Formatter
+    },
+    fs::{
+        canonicalize,
+        read_to_string
+    },
+    path::{
+        Component,
+        Path,
+        PathBuf
+    },
+    rc::Rc
+};
+
+/// Index of a module in `Modules::modules`
+pub type ModuleId = usize;
+
+/// The module that code passed to the interpreter directly is evaluated in
+pub const MAIN: ModuleId = 0;
+
+/// Evaluates one top-level expression of a module with a particular backend
+pub type EvalFn = fn(&mut Buffer, &mut Modules, ModuleId, &Expr) -> Result<Object, Box<dyn Error>>;
+
+/// A namespace of global definitions, usually loaded from a file
+pub struct Module {
+    pub name: String,
+    /// Canonical path of the module's file, or `None` for the main module
+    pub path: Option<PathBuf>,
+    pub names: HashMap<String, Object>,
+    /// Names of types and enum variants, which are separate from other names
+    pub types: HashMap<String, TypeDef>
+}
+
+/// A handle to a loaded module, bound by `(use "file")`
+#[derive(Clone)]
+pub struct ModuleRef {
+    pub id: ModuleId,
+    pub name: Rc<str>
+}
+
+impl PartialEq for ModuleRef {
+    fn eq(&self, other: &ModuleRef) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for ModuleRef {}
+
+impl Debug for ModuleRef {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(form, "<module {}>", self.name)
+    }
+}
+
+/// Every module loaded by an interpreter, along with the builtins they share
+pub struct Modules {
+    /// Names visible from every module unless a module defines its own
+    pub builtins: HashMap<String, Object>,
+    pub modules: Vec<Module>,
+    /// Loaded modules by canonical path, so each file is only evaluated once
+    paths: HashMap<PathBuf, ModuleId>,
+    /// Modules whose files are being evaluated, outermost first
+    loading: Vec<ModuleId>,
+    /// Directories searched for modules that aren't next to the file importing them
+    pub search_path: Vec<PathBuf>
+}
+
+impl Default for Modules {
+    fn default() -> Modules {
+        Modules::new()
+    }
+}
+
+impl Modules {
+    /// Creates the registry with an empty main module
+    pub fn new() -> Modules {
+        Modules {
+            builtins: HashMap::new(),
+            modules: vec![Module {
+                name: "main".to_owned(),
+                path: None,
+                names: HashMap::new(),
+                types: HashMap::new()
+            }],
+            paths: HashMap::new(),
+            loading: Vec::new(),
+            search_path: Vec::new()
+        }
+    }
+
+    /// Looks up a global name as seen from `module`
+    pub fn get(&self, module: ModuleId, name: &str) -> Option<&Object> {
+        self.modules[module].names.get(name).or_else(|| self.builtins.get(name))
+    }
+
+    pub fn define(&mut self, module: ModuleId, name: String, obj: Object) {
+        self.modules[module].names.insert(name, obj);
+    }
+
+    pub fn define_type(&mut self, module: ModuleId, name: String, def: TypeDef) {
+        self.modules[module].types.insert(name, def);
+    }
+
+    /// Looks up a type name as seen from `module`. A qualified name like
+    /// `geo.Point` is looked up in the module bound to `geo`.
+    pub fn find_type(&self, buf: &Buffer, module: ModuleId, path: &str, span: Span) -> Result<TypeDef, Box<dyn Error>> {
+        let mut scope = module;
+        let mut segments: Vec<&str> = path.split('.').collect();
+        let name = segments.pop().unwrap_or_default();
+
+        for segment in segments {
+            scope = match self.modules[scope].names.get(segment) {
+                Some(Object::Module(m)) => m.id,
+                _ => {output::error_at(buf, span, ErrorKind::Name, format!("`{}` is not a module", segment))?; scope}
+            };
+        }
+
+        return Ok(match self.modules[scope].types.get(name) {
+            Some(def) => def.clone(),
+            None => {output::error_at(buf, span, ErrorKind::Name, format!("Undefined type: `{}`", path))?; TypeDef::Enum(RecordType::new(name.into()))}
+        });
+    }
+
+    /// Looks up `member` in the module that `obj` refers to, or the field `member` if it's a record
+    pub fn member(&self, buf: &Buffer, obj: &Object, member: &str, span: Span) -> Result<Object, Box<dyn Error>> {
+        let module = match obj {
+            Object::Module(m) => m,
+            Object::Record(r) => return Ok(match r.field(member) {
+                Some(obj) => obj.clone(),
+                None => {output::error_at(buf, span, ErrorKind::Name, format!("Struct `{}` has no field `{}`", r.def.ty.name, member))?; Object::None}
+            }),
+            _ => {output::error_at(buf, span, ErrorKind::Type, format!("`{:?}` is not a module or record", obj))?; return Ok(Object::None)}
+        };
+
+        return Ok(match self.modules[module.id].names.get(member) {
+            Some(obj) => obj.clone(),
+            None => {output::error_at(buf, span, ErrorKind::Name, format!("Module `{}` has no member `{}`", module.name, member))?; Object::None}
+        });
+    }
+
+    /// Loads the module named by a `use` expression in `module`, then binds
+    /// either the module itself or the names it imports
+    pub fn use_module(&mut self, buf: &mut Buffer, module: ModuleId, decl: &UseDecl, span: Span, eval: EvalFn) -> Result<(), Box<dyn Error>> {
+        let path = self.find(buf, &decl.path, span)?;
+        let canonical = canonicalize(&path).unwrap_or_else(|_| path.clone());
+        let id = match self.paths.get(&canonical) {
+            Some(&id) => {
+                if self.loading.contains(&id) {self.cycle_error(buf, id, span)?}
+                id
+            },
+            None => self.load(buf, path, canonical, span, eval)?
+        };
+
+        match &decl.imports {
+            Some(imports) => for name in imports {
+                // Types are imported along with their constructors, and an enum's name on its own
+                if let Some(def) = self.modules[id].types.get(name).cloned() {
+                    self.define_type(module, name.clone(), def);
+                    if !self.modules[id].names.contains_key(name) {continue;}
+                }
+
+                let obj = self.member(buf, &self.module_ref(id), name, span)?;
+                self.define(module, name.clone(), obj);
+            },
+            None => {
+                let name = self.modules[id].name.clone();
+                let obj = self.module_ref(id);
+                self.define(module, name, obj);
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Finds the file for the module at `path`, looking next to the file
+    /// containing `span` and then in the search path
+    fn find(&self, buf: &Buffer, path: &str, span: Span) -> Result<PathBuf, Box<dyn Error>> {
+        let mut file = PathBuf::from(path);
+        if file.extension().is_none() {file.set_extension("sp");}
+
+        let mut tried = Vec::new();
+        if file.is_absolute() {tried.push(file.clone());}
+        else {
+            // Code that didn't come from a file imports relative to the working directory
+            let importer = buf.source_pos(span.start).and_then(|(source, _)| source.name.as_ref());
+            let dir = importer.and_then(|name| Path::new(name).parent()).unwrap_or_else(|| Path::new(""));
+            tried.push(dir.join(&file));
+
+            // Paths starting with `.` or `..` are only looked for next to the importer
+            if !matches!(file.components().next(), Some(Component::CurDir) | Some(Component::ParentDir)) {
+                tried.extend(self.search_path.iter().map(|dir| dir.join(&file)));
+            }
+        }
+
+        if let Some(found) = tried.iter().find(|p| p.is_file()) {
+            return Ok(found.clone());
+        }
+
+        let tried: Vec<String> = tried.iter().map(|p| format!("\n    {}", p.display())).collect();
+        output::error_at(buf, span, ErrorKind::Import, format!("Couldn't find module `{}`, tried:{}", path, tried.concat()))?;
+
+        return Ok(file);
+    }
+
+    fn module_ref(&self, id: ModuleId) -> Object {
+        Object::Module(ModuleRef {
+            id,
+            name: self.modules[id].name.as_str().into()
+        })
+    }
+
+    /// Evaluates a module's file into a new namespace
+    fn load(&mut self, buf: &mut Buffer, path: PathBuf, canonical: PathBuf, span: Span, eval: EvalFn) -> Result<ModuleId, Box<dyn Error>> {
+        let src = match read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) => {output::error_at(buf, span, ErrorKind::Io, format!("Couldn't read `{}`: {}", path.display(), e))?; String::new()}
+        };
+        let id = self.modules.len();
+
+        self.modules.push(Module {
+            name: path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+            path: Some(canonical.clone()),
+            names: HashMap::new(),
+            types: HashMap::new()
+        });
+        self.paths.insert(canonical, id);
+
+        // The module's code goes at the end of the buffer, after which the importer carries on where it was
+        let (index, len) = (buf.index, buf.len);
+        buf.append(Some(&path.display().to_string()), &src);
+
+        self.loading.push(id);
+        let res = self.eval_source(buf, id, eval);
+        self.loading.pop();
+
+        buf.index = index;
+        buf.len = len;
+        if let Err(e) = res {
+            // Let a later `use` try again rather than finding a half-evaluated module
+            self.paths.retain(|_, &mut m| m != id);
+            return Err(e);
+        }
+
+        return Ok(id);
+    }
+
+    /// Evaluates the rest of the buffer as the file at `path` in the main module. The
+    /// file counts as loaded, so a module that uses it gets the main module rather than
+    /// evaluating it again, and using it while it's running is an import cycle.
+    pub fn eval_main_file(&mut self, buf: &mut Buffer, path: &Path, eval: EvalFn) -> Result<Object, Box<dyn Error>> {
+        let canonical = canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let main = &mut self.modules[MAIN];
+        main.name = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        main.path = Some(canonical.clone());
+        self.paths.insert(canonical, MAIN);
+
+        self.loading.push(MAIN);
+        let res = self.eval_source(buf, MAIN, eval);
+        self.loading.pop();
+
+        return res;
+    }
+
+    fn cycle_error(&self, buf: &Buffer, id: ModuleId, span: Span) -> Result<(), Box<dyn Error>> {
+        let start = self.loading.iter().position(|&m| m == id).unwrap_or(0);
+        let cycle: Vec<&str> = self.loading[start..].iter()
+            .chain(Some(&id))
+            .map(|&m| self.modules[m].name.as_str())
+            .collect();
+
+        return output::error_at(buf, span, ErrorKind::Import, format!("Import cycle: {}", cycle.join(" -> ")));
+    }
+
+    /// Parses and evaluates the rest of the buffer in `module`, returning the value of the last expression
+    pub fn eval_source(&mut self, buf: &mut Buffer, module: ModuleId, eval: EvalFn) -> Result<Object, Box<dyn Error>> {
+        let mut res = Object::None;
+        while buf.index < buf.len {
+            let mut expr = parse_next_expr(buf)?;
+            resolve_types(buf, self, module, &mut expr)?;
+            res = eval(buf, self, module, &expr)?;
+        }
+
+        return Ok(res);
+    }
+}
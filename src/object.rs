@@ -1,41 +1,558 @@
-use crate::call::Func;
+use crate::{
+    call::{
+        Func,
+        FuncKind,
+        Param
+    },
+    module::ModuleRef,
+    tokenizer::Span
+};
+
+use num_bigint::BigInt;
 
-use std::fmt::{
-    Debug,
-    Formatter
+use std::{
+    convert::TryFrom,
+    collections::{
+        hash_map::DefaultHasher,
+        HashMap
+    },
+    hash::{
+        Hash,
+        Hasher
+    },
+    iter::FromIterator,
+    mem::discriminant,
+    error::Error,
+    fmt::{
+        Debug,
+        Display,
+        Formatter
+    },
+    rc::Rc,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering
+    }
 };
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Type {
     Function,
     Int,
+    BigInt,
+    Float,
     Bool,
     Str,
     List,
+    Map,
     None,
+    Module,
+    /// A record or enum type defined with `struct` or `enum`
+    Struct(RecordType),
+    /// A type named in an annotation, before it's resolved to the `struct` or
+    /// `enum` it refers to
+    Named(Rc<str>, Span),
+    /// `list[t]`, a list whose items all have type `t`
+    ListOf(Box<Type>),
+    /// `(fn params... -> ret)`, a function that can be called with arguments of
+    /// the parameter types and returns the return type
+    Fn(Vec<Type>, Box<Type>),
+    /// `a | b`, which any value of one of the types has. `?t` is short for `t | none`.
+    Union(Vec<Type>),
     Any
 }
 
+impl Type {
+    /// Combines types into a union, flattening nested unions and leaving out repeats
+    pub fn union(types: Vec<Type>) -> Type {
+        let mut flat = Vec::<Type>::new();
+        for t in types {
+            let members = match t {
+                Type::Union(members) => members,
+                t => vec![t]
+            };
+            for m in members {
+                if !flat.contains(&m) {flat.push(m);}
+            }
+        }
+
+        if flat.contains(&Type::Any) {return Type::Any;}
+
+        return if flat.len() == 1 {flat.remove(0)} else {Type::Union(flat)};
+    }
+
+    /// Checks whether `obj` has this type. The items of a list are checked one by
+    /// one, and a function by the types in its signature.
+    pub fn matches(&self, obj: &Object) -> bool {
+        match (self, obj) {
+            (Type::Any, _) => true,
+            (Type::Union(types), _) => types.iter().any(|t| t.matches(obj)),
+            (Type::ListOf(item), Object::List(ls)) => ls.iter().all(|o| item.matches(o)),
+            (Type::Fn(params, ret), Object::Function(f)) => f.has_signature(params, ret),
+            _ => obj.get_type() == *self
+        }
+    }
+
+    /// Checks whether a value of this type can be used where `other` is expected.
+    /// Like in gradual typing, `any` fits every type and every type fits `any`, so
+    /// functions without annotations can be passed for any signature.
+    pub fn fits(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Union(types), _) => types.iter().all(|t| t.fits(other)),
+            (_, Type::Union(types)) => types.iter().any(|t| self.fits(t)),
+            (Type::ListOf(a), Type::ListOf(b)) => a.fits(b),
+            // A plain `list` or `function` could hold anything
+            (Type::ListOf(_), Type::List) | (Type::List, Type::ListOf(_)) => true,
+            (Type::Fn(..), Type::Function) | (Type::Function, Type::Fn(..)) => true,
+            // A function fits a signature if it accepts at least the argument types and returns at most the return type
+            (Type::Fn(params, ret), Type::Fn(other_params, other_ret)) => {
+                params.len() == other_params.len()
+                    && other_params.iter().zip(params).all(|(o, p)| o.fits(p))
+                    && ret.fits(other_ret)
+            },
+            _ => self == other
+        }
+    }
+}
+
 impl Debug for Type {
     fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(form, "{}", match self {
-            Type::Any => "any",
-            Type::Function => "function",
-            Type::Int => "int",
-            Type::Bool => "bool",
-            Type::Str => "string",
-            Type::List => "list",
-            Type::None => "none_t"
-        })
+        match self {
+            Type::Struct(ty) => write!(form, "{}", ty.name),
+            Type::Named(name, _) => write!(form, "{}", name),
+            Type::Any => write!(form, "any"),
+            Type::Function => write!(form, "function"),
+            Type::Int => write!(form, "int"),
+            Type::BigInt => write!(form, "bigint"),
+            Type::Float => write!(form, "float"),
+            Type::Bool => write!(form, "bool"),
+            Type::Str => write!(form, "string"),
+            Type::List => write!(form, "list"),
+            Type::Map => write!(form, "map"),
+            Type::None => write!(form, "none_t"),
+            Type::Module => write!(form, "module"),
+            Type::ListOf(item) => write!(form, "list[{:?}]", item),
+            Type::Fn(params, ret) => {
+                write!(form, "(fn")?;
+                for p in params {write!(form, " {:?}", p)?;}
+                if **ret != Type::Any {write!(form, " -> {:?}", ret)?;}
+                write!(form, ")")
+            },
+            Type::Union(types) => {
+                for (i, t) in types.iter().enumerate() {
+                    if i > 0 {write!(form, " | ")?;}
+                    write!(form, "{:?}", t)?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Object {
     Function(Func),
     Int(i64),
+    /// Integers of any size, which share their digits when copied
+    BigInt(Rc<BigInt>),
+    Float(f64),
     Bool(bool),
     Str(String),
-    List(Vec<Object>),
-    None
+    /// Lists are immutable, so they share their contents when copied
+    List(Rc<[Object]>),
+    Map(Map),
+    None,
+    Module(ModuleRef),
+    Record(Record)
+}
+
+impl Object {
+    /// Returns the runtime type of an object
+    pub fn get_type(&self) -> Type {
+        match self {
+            Object::Function(_) => Type::Function,
+            Object::Int(_) => Type::Int,
+            Object::BigInt(_) => Type::BigInt,
+            Object::Float(_) => Type::Float,
+            Object::Bool(_) => Type::Bool,
+            Object::Str(_) => Type::Str,
+            Object::List(_) => Type::List,
+            Object::Map(_) => Type::Map,
+            Object::None => Type::None,
+            Object::Module(_) => Type::Module,
+            Object::Record(r) => Type::Struct(r.def.record_type())
+        }
+    }
+
+    /// Returns the most specific type of an object for error messages: a
+    /// function's signature, or a list's type along with the types of its items
+    pub fn describe_type(&self) -> Type {
+        match self {
+            Object::Function(f) if f.rest_index().is_none() => Type::Fn(
+                f.params.iter().map(|p| p.arg_type.clone()).collect(),
+                Box::new(f.ret_type.clone())
+            ),
+            Object::List(ls) if !ls.is_empty() => Type::ListOf(Box::new(Type::union(ls.iter().map(Object::describe_type).collect()))),
+            _ => self.get_type()
+        }
+    }
+}
+
+/// An immutable map from keys of any type to values, which keeps its keys in
+/// the order they were first added
+#[derive(Clone, Default)]
+pub struct Map {
+    table: Rc<Table>
+}
+
+/// The entries of a map along with an index from key hashes to their positions
+#[derive(Clone, Default)]
+struct Table {
+    entries: Vec<(Object, Object)>,
+    /// The position of the last entry added for each key hash
+    index: HashMap<u64, usize>,
+    /// For each entry, the position of the previous entry with the same key hash
+    collisions: Vec<Option<usize>>
+}
+
+impl Table {
+    fn find(&self, hash: u64, key: &Object) -> Option<usize> {
+        let mut pos = self.index.get(&hash).copied();
+        while let Some(i) = pos {
+            if self.entries[i].0 == *key {return Some(i);}
+            pos = self.collisions[i];
+        }
+
+        return None;
+    }
+
+    fn insert(&mut self, key: Object, value: Object) {
+        let hash = hash_key(&key);
+        match self.find(hash, &key) {
+            Some(i) => self.entries[i].1 = value,
+            None => {
+                self.collisions.push(self.index.insert(hash, self.entries.len()));
+                self.entries.push((key, value));
+            }
+        }
+    }
+}
+
+impl Map {
+    pub fn get(&self, key: &Object) -> Option<&Object> {
+        self.table.find(hash_key(key), key).map(|i| &self.table.entries[i].1)
+    }
+
+    pub fn has(&self, key: &Object) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns a copy of the map with `key` bound to `value`, replacing any previous value
+    pub fn assoc(&self, key: Object, value: Object) -> Map {
+        let mut table = (*self.table).clone();
+        table.insert(key, value);
+
+        return Map {table: Rc::new(table)};
+    }
+
+    /// Returns a copy of the map without `key`
+    pub fn dissoc(&self, key: &Object) -> Map {
+        if !self.has(key) {return self.clone();}
+
+        return self.iter().filter(|(k, _)| k != key).cloned().collect();
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Object, Object)> {
+        self.table.entries.iter()
+    }
+}
+
+/// Later entries replace earlier ones with the same key
+impl FromIterator<(Object, Object)> for Map {
+    fn from_iter<I: IntoIterator<Item = (Object, Object)>>(iter: I) -> Map {
+        let mut table = Table::default();
+        for (key, value) in iter {
+            table.insert(key, value);
+        }
+
+        return Map {table: Rc::new(table)};
+    }
+}
+
+/// Hashes a map key so that keys which are equal always have the same hash
+fn hash_key(key: &Object) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_object(key, &mut hasher);
+
+    return hasher.finish();
+}
+
+fn hash_object(obj: &Object, hasher: &mut DefaultHasher) {
+    discriminant(obj).hash(hasher);
+    match obj {
+        // Functions compare their definitions by identity, so only the name is stable to hash
+        Object::Function(f) => f.name.hash(hasher),
+        Object::Int(i) => i.hash(hasher),
+        Object::BigInt(i) => i.hash(hasher),
+        // `0.0` and `-0.0` are equal
+        Object::Float(f) => (if *f == 0.0 {0.0f64} else {*f}).to_bits().hash(hasher),
+        Object::Bool(b) => b.hash(hasher),
+        Object::Str(s) => s.hash(hasher),
+        Object::List(ls) => {
+            ls.len().hash(hasher);
+            for item in ls.iter() {hash_object(item, hasher);}
+        },
+        // Maps are equal whatever order their entries are in, so their hashes are combined in an order-independent way
+        Object::Map(m) => m.iter().map(|(k, v)| {
+            let mut entry = DefaultHasher::new();
+            hash_object(k, &mut entry);
+            hash_object(v, &mut entry);
+            entry.finish()
+        }).fold(0u64, u64::wrapping_add).hash(hasher),
+        Object::None => (),
+        Object::Module(m) => m.id.hash(hasher),
+        Object::Record(r) => {
+            Rc::as_ptr(&r.def).hash(hasher);
+            for value in r.values.iter() {hash_object(value, hasher);}
+        }
+    }
+}
+
+/// Maps are equal if they have the same entries, whatever order they were added in
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl Debug for Map {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        form.debug_map().entries(self.iter().map(|(k, v)| (k, v))).finish()
+    }
+}
+
+/// Identifies a `struct`, `enum` or variant by the definition that made it,
+/// so types with the same name from different modules are told apart
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RecordType {
+    pub name: Rc<str>,
+    id: usize
+}
+
+impl RecordType {
+    /// Creates an identity distinct from every other definition's
+    pub fn new(name: Rc<str>) -> RecordType {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        RecordType {
+            name,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        }
+    }
 }
+
+/// A record type defined with `(struct Name field: type...)`, or a variant of an enum
+#[derive(Debug)]
+pub struct StructDef {
+    pub ty: RecordType,
+    pub fields: Vec<Param>,
+    /// The enum this is a variant of, if any
+    pub parent: Option<Rc<EnumDef>>
+}
+
+/// An enum type defined with `(enum Name variants...)`
+#[derive(Debug)]
+pub struct EnumDef {
+    pub ty: RecordType,
+    pub variants: Vec<RecordType>
+}
+
+/// What a type name refers to within a module
+#[derive(Clone, Debug)]
+pub enum TypeDef {
+    /// A `struct`, whose name is both a type and a pattern
+    Struct(RecordType),
+    /// An `enum`, whose name is a type
+    Enum(RecordType),
+    /// A variant of an enum, whose name is a pattern. Holds the variant and its enum.
+    Variant(RecordType, RecordType)
+}
+
+impl StructDef {
+    /// The type of records made by this definition, which is its enum's type for a variant
+    pub fn record_type(&self) -> RecordType {
+        self.parent.as_ref().map_or(&self.ty, |p| &p.ty).clone()
+    }
+
+
+    /// The value bound to the type's name: its constructor, or the only value
+    /// of an enum variant without fields
+    pub fn value(self: &Rc<StructDef>) -> Object {
+        match &self.parent {
+            Some(_) if self.fields.is_empty() => Object::Record(Record {
+                def: self.clone(),
+                values: Rc::from(Vec::new())
+            }),
+            _ => Object::Function(self.constructor())
+        }
+    }
+
+    /// Creates the function that builds records of this type from their fields
+    pub fn constructor(self: &Rc<StructDef>) -> Func {
+        let def = self.clone();
+
+        Func {
+            name: self.ty.name.to_string(),
+            params: self.fields.clone(),
+            ret_type: Type::Struct(self.record_type()),
+            kind: FuncKind::Native(Rc::new(move |args| Ok(Object::Record(Record {
+                def: def.clone(),
+                values: args.into()
+            }))))
+        }
+    }
+}
+
+/// A value of a record type, holding its fields in the order they were declared
+#[derive(Clone)]
+pub struct Record {
+    pub def: Rc<StructDef>,
+    pub values: Rc<[Object]>
+}
+
+impl Record {
+    pub fn field(&self, name: &str) -> Option<&Object> {
+        let idx = self.def.fields.iter().position(|f| f.name == name)?;
+
+        return self.values.get(idx);
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Object)> {
+        self.def.fields.iter().map(|f| f.name.as_str()).zip(self.values.iter())
+    }
+}
+
+/// Records are only equal to records made by the same `struct` definition
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        Rc::ptr_eq(&self.def, &other.def) && self.values == other.values
+    }
+}
+
+impl Debug for Record {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let mut s = form.debug_struct(&self.def.ty.name);
+        for (name, value) in self.fields() {s.field(name, value);}
+
+        return s.finish();
+    }
+}
+
+/// Error returned when converting an `Object` to a Rust value of a different type
+#[derive(Debug)]
+pub struct ConversionError {
+    pub expected: Type,
+    pub got: Type
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(form, "Expected type `{:?}`, got type `{:?}`", self.expected, self.got)
+    }
+}
+
+impl Error for ConversionError {}
+
+impl From<i64> for Object {
+    fn from(i: i64) -> Object {
+        Object::Int(i)
+    }
+}
+
+impl From<BigInt> for Object {
+    fn from(n: BigInt) -> Object {
+        Object::BigInt(Rc::new(n))
+    }
+}
+
+impl From<f64> for Object {
+    fn from(f: f64) -> Object {
+        Object::Float(f)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(b: bool) -> Object {
+        Object::Bool(b)
+    }
+}
+
+impl From<String> for Object {
+    fn from(s: String) -> Object {
+        Object::Str(s)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(s: &str) -> Object {
+        Object::Str(s.to_owned())
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Object {
+        Object::None
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(v: Vec<T>) -> Object {
+        Object::List(v.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(o: Option<T>) -> Object {
+        match o {
+            Some(obj) => obj.into(),
+            None => Object::None
+        }
+    }
+}
+
+/// Implements `TryFrom<Object>` for a Rust type held by a single `Object` variant
+macro_rules! impl_try_from {
+    ($t:ty, $variant:ident, $type:ident, $val:ident => $conv:expr) => {
+        impl TryFrom<Object> for $t {
+            type Error = ConversionError;
+
+            fn try_from(obj: Object) -> Result<$t, ConversionError> {
+                match obj {
+                    Object::$variant($val) => Ok($conv),
+                    _ => Err(ConversionError {
+                        expected: Type::$type,
+                        got: obj.get_type()
+                    })
+                }
+            }
+        }
+    };
+}
+
+impl_try_from!(i64, Int, Int, i => i);
+impl_try_from!(BigInt, BigInt, BigInt, n => (*n).clone());
+impl_try_from!(f64, Float, Float, f => f);
+impl_try_from!(bool, Bool, Bool, b => b);
+impl_try_from!(String, Str, Str, s => s);
+impl_try_from!(Vec<Object>, List, List, ls => ls.to_vec());
+impl_try_from!(Map, Map, Map, m => m);
+impl_try_from!(Func, Function, Function, f => f);
@@ -1,4 +1,5 @@
 mod funcdef;
+mod matchexpr;
 pub mod op;
 pub mod output;
 
@@ -6,108 +7,55 @@ use crate::{
     tokenizer::{
         Token,
         Buffer,
+        Span,
         get_tok
     },
-    object::{
-        Type,
-        Object
+    ast::{
+        Expr,
+        ExprKind,
+        Binding,
+        LetKind,
+        Pattern,
+        UseDecl
     },
-    call::{
-        Arg,
-        CallInfo,
-        Func,
-        call_function
-    }
+    error::ErrorKind
 };
 
 use std::{
-    collections::HashMap,
     error::Error,
-    process::exit,
-    fs::read,
+    rc::Rc
 };
 
-pub fn eat_expr<'a>(buf: &'a mut Buffer) -> Result<(), Box<dyn Error>> {
-    let mut lparens = 0usize;
-    let mut lbrackets = 0usize;
-
-    let mut tok = get_tok(buf)?;
-    match tok {
-        Token::LParen => lparens += 1,
-        Token::RParen => lparens -= 1,
-        Token::LBracket => lbrackets += 1,
-        Token::RBracket => lbrackets -= 1,
-        _ => ()
-    }
-
-    while lparens > 0 || lbrackets > 0 {
-        tok = get_tok(buf)?;
-        match tok {
-            Token::LParen => lparens += 1,
-            Token::RParen => lparens -= 1,
-            Token::LBracket => lbrackets += 1,
-            Token::RBracket => lbrackets -= 1,
-            _ => ()
-        }
-    }
-
-    return Ok(());
-}
+/// Parses the expression starting with `tok` into an AST
+pub fn parse_expr(buf: &mut Buffer, tok: Token) -> Result<Expr, Box<dyn Error>> {
+    let span = buf.span();
 
-pub fn match_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>, tok: Token) -> Result<Object, Box<dyn Error>> {
     Ok(match tok {
-        Token::Name(n) => parse_single_name_expr(buf, names, call_stack, n)?,
-        Token::Num(n) => Object::Int(n.parse()?),
-        Token::Str(s) => Object::Str(s),
-        Token::True => Object::Bool(true),
-        Token::False => Object::Bool(false),
-        Token::None => Object::None,
-        Token::LParen => parse_paren_expr(buf, names, call_stack, scope_stack)?,
-        Token::LBracket => parse_list_expr(buf, names, call_stack, scope_stack)?,
-        Token::Empty => Object::None,
-        _ => {output::error(buf, format!("Expected expression, got `{:?}`", tok))?; Object::None}
+        Token::Name(n) => parse_name_expr(buf, n, span)?,
+        Token::Num(i) => Expr::new(ExprKind::Int(i), span),
+        Token::Float(f) => Expr::new(ExprKind::Float(f), span),
+        Token::BigNum(n) => Expr::new(ExprKind::BigInt(Rc::new(n)), span),
+        Token::Str(s) => Expr::new(ExprKind::Str(s), span),
+        Token::True => Expr::new(ExprKind::Bool(true), span),
+        Token::False => Expr::new(ExprKind::Bool(false), span),
+        Token::None => Expr::new(ExprKind::None, span),
+        Token::LParen => parse_paren_expr(buf)?,
+        Token::LBracket => parse_list_expr(buf)?,
+        Token::LBrace => parse_map_expr(buf)?,
+        Token::Empty => Expr::new(ExprKind::None, span),
+        _ => {output::error(buf, format!("Expected expression, got `{:?}`", tok))?; Expr::new(ExprKind::None, span)}
     })
 }
 
-fn parse_paren_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>) -> Result<Object, Box<dyn Error>> {
+/// Parses the next expression in `buf`
+pub fn parse_next_expr(buf: &mut Buffer) -> Result<Expr, Box<dyn Error>> {
     let tok = get_tok(buf)?;
 
-    let res = match tok {
-        Token::Name(n) => parse_name_expr(buf, names, call_stack, scope_stack, n)?,
-        Token::Def => funcdef::parse_def_expr(buf, names)?,
-        Token::If => parse_if_expr(buf, names, call_stack, scope_stack)?,
-        Token::Lambda => funcdef::parse_lambda_expr(buf)?,
-        Token::Let => parse_let_expr(buf, names, call_stack, scope_stack)?,
-        Token::LParen => {
-            let temp = parse_paren_expr(buf, names, call_stack, scope_stack)?;
-            match temp {
-                Object::Function(f) => parse_call_expr(buf, names, call_stack, scope_stack, &f)?,
-                _ => temp
-            }
-        },
-        Token::Op(o) => op::parse_op_expr(buf, names, call_stack, scope_stack, o)?,
-        Token::Use => {
-            let tok = get_tok(buf)?;
-
-            let slice = {
-                if let Token::Str(filename) = tok {read(filename.replace("~", &std::env::var("SPUTTER_INCLUDE")?))?}
-                else {output::error(buf, format!("Expected string, got {:?}", tok))?; Vec::new()}
-            };
-            
-            let tok = get_tok(buf)?;
-
-            match tok {
-                Token::RParen => (),
-                _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
-            };
-
-            buf.splice(&slice);
-
-            return Ok(Object::None);
-        },
-        _ => {match_expr(buf, names, call_stack, scope_stack, tok)?}
-    };
+    return parse_expr(buf, tok);
+}
 
+/// Consumes a `)`, reporting an error if the next token is anything else
+fn expect_rparen(buf: &mut Buffer) -> Result<(), Box<dyn Error>> {
     let tok = get_tok(buf)?;
 
     match tok {
@@ -115,146 +63,224 @@ fn parse_paren_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>
         _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
     };
 
-    return Ok(res);
+    return Ok(());
 }
 
-fn parse_list_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>) -> Result<Object, Box<dyn Error>> {
-    let mut ls = Vec::<Object>::new();
+/// Parses a name, splitting qualified names like `vec.push` into member lookups
+fn parse_name_expr(buf: &Buffer, name: String, span: Span) -> Result<Expr, Box<dyn Error>> {
+    if !name.contains('.') {return Ok(Expr::new(ExprKind::Name(name), span));}
 
-    loop {
-        let tok = get_tok(buf)?;
-        ls.push(match tok {
-            Token::RBracket => {break;},
-            _ => match_expr(buf, names, call_stack, scope_stack, tok)?
-        });
+    let mut parts = name.split('.');
+    let mut expr = Expr::new(ExprKind::Name(parts.next().unwrap_or_default().to_owned()), span);
+    for part in parts {
+        if part.is_empty() {output::error(buf, format!("Invalid qualified name: `{}`", name))?}
+        expr = Expr::new(ExprKind::Member(Box::new(expr), part.to_owned()), span);
     }
 
-    return Ok(Object::List(ls));
+    return Ok(expr);
 }
 
-fn parse_single_name_expr<'a>(buf: &Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, name: String) -> Result<Object, Box<dyn Error>> {
-    let params = match call_stack.len() > 0 {
-        true => call_stack[call_stack.len() - 1].params.clone(),
-        false => Vec::new()
-    };
-    let args = match call_stack.len() > 0 {
-        true => call_stack[call_stack.len() - 1].args.clone(),
-        false => Vec::new()
-    };
+fn parse_paren_expr(buf: &mut Buffer) -> Result<Expr, Box<dyn Error>> {
+    let start = buf.span();
+    let tok = get_tok(buf)?;
 
-    let n: Object;
-    let mut param_names = Vec::<String>::new();
-    for p in &params {param_names.push(p.name.clone());}
+    let kind = match tok {
+        Token::Def => funcdef::parse_def_expr(buf)?,
+        Token::If => parse_if_expr(buf)?,
+        Token::Lambda => funcdef::parse_lambda_expr(buf)?,
+        Token::Let(kind) => parse_let_expr(buf, kind)?,
+        Token::Op(o) => op::parse_op_expr(buf, o)?,
+        Token::Use => parse_use_expr(buf)?,
+        Token::Struct => funcdef::parse_struct_expr(buf)?,
+        Token::Enum => funcdef::parse_enum_expr(buf)?,
+        Token::Match => matchexpr::parse_match_expr(buf)?,
+        Token::Do => parse_do_expr(buf)?,
+        _ => {
+            let head = parse_expr(buf, tok)?;
+            let mut args = Vec::<Expr>::new();
+            let mut keywords = Vec::<(String, Expr)>::new();
+
+            loop {
+                let tok = get_tok(buf)?;
+                match tok {
+                    Token::RParen => break,
+                    Token::Empty => {output::error(buf, "Expected `)`, got end of input".to_owned())?; break},
+                    Token::Colon => {
+                        let tok = get_tok(buf)?;
+                        let name = match tok {
+                            Token::Name(n) => n,
+                            _ => {output::error(buf, format!("Expected parameter name after `:`, got `{:?}`", tok))?; String::new()}
+                        };
+
+                        keywords.push((name, parse_next_expr(buf)?));
+                    },
+                    _ if !keywords.is_empty() => {output::error(buf, "Positional arguments have to come before keyword arguments".to_owned())?; break},
+                    _ => args.push(parse_expr(buf, tok)?)
+                }
+            }
 
-    if param_names.contains(&name) {
-        n = args[
-            params.iter().position(|r| *r.name == name).unwrap()
-        ].val.clone();
-    }
-    else {
-        if names.contains_key(&name) == false {output::error(buf, format!("Undefined name: `{}`", name))?}
-        n = names[&name].clone();
-    }
+            return Ok(Expr::new(ExprKind::Call(Box::new(head), args, keywords), start.to(buf.span())));
+        }
+    };
+
+    expect_rparen(buf)?;
 
-    return Ok(n);
+    return Ok(Expr::new(kind, start.to(buf.span())));
 }
 
-fn parse_name_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>, name: String) -> Result<Object, Box<dyn Error>> {
-    let params = match call_stack.len() > 0 {
-        true => call_stack[call_stack.len() - 1].params.clone(),
-        false => Vec::new()
-    };
-    let args = match call_stack.len() > 0 {
-        true => call_stack[call_stack.len() - 1].args.clone(),
-        false => Vec::new()
+fn parse_use_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
+    let tok = get_tok(buf)?;
+    let path = match tok {
+        Token::Str(path) => path,
+        // Bare module names like `lib.vec` stand for the path `lib/vec`
+        Token::Name(name) => name.replace('.', "/"),
+        _ => {output::error(buf, format!("Expected module name or path, got `{:?}`", tok))?; String::new()}
     };
 
-    let n: Object;
-    let mut param_names = Vec::<String>::new();
-    for p in &params {param_names.push(p.name.clone());}
+    // An optional list of names to import follows the path
+    let mut imports = None;
+    let idx = buf.index;
+    if let Token::LParen = get_tok(buf)? {
+        let mut names = Vec::new();
+
+        loop {
+            let tok = get_tok(buf)?;
+            match tok {
+                Token::RParen => break,
+                Token::Name(n) => names.push(n),
+                _ => {output::error(buf, format!("Expected name, got `{:?}`", tok))?; break}
+            }
+        }
 
-    if param_names.contains(&name) {
-        n = args[
-            params.iter().position(|r| *r.name == name).unwrap()
-        ].val.clone();
+        imports = Some(names);
     }
-    else {
-        if names.contains_key(&name) == false {output::error(buf, format!("Undefined name: `{}`", name))?}
-        n = names[&name].clone();
+    else {buf.index = idx}
+
+    return Ok(ExprKind::Use(UseDecl {
+        path,
+        imports
+    }));
+}
+
+fn parse_list_expr(buf: &mut Buffer) -> Result<Expr, Box<dyn Error>> {
+    let start = buf.span();
+    let mut ls = Vec::<Expr>::new();
+
+    loop {
+        let tok = get_tok(buf)?;
+        match tok {
+            Token::RBracket => break,
+            Token::Empty => {output::error(buf, "Expected `]`, got end of input".to_owned())?; break},
+            _ => ls.push(parse_expr(buf, tok)?)
+        }
     }
 
-    return Ok(match n {
-        Object::Function(func) => parse_call_expr(buf, names, call_stack, scope_stack, &func)?,
-        _ => n
-    });
+    return Ok(Expr::new(ExprKind::List(ls), start.to(buf.span())));
 }
 
-fn parse_if_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>) -> Result<Object, Box<dyn Error>> {
-    let mut res = Object::None;
+fn parse_map_expr(buf: &mut Buffer) -> Result<Expr, Box<dyn Error>> {
+    let start = buf.span();
+    let mut entries = Vec::<(Expr, Expr)>::new();
 
-    let mut tok = get_tok(buf)?;
+    loop {
+        let tok = get_tok(buf)?;
+        let key = match tok {
+            Token::RBrace => break,
+            Token::Empty => {output::error(buf, "Expected `}`, got end of input".to_owned())?; break},
+            _ => parse_expr(buf, tok)?
+        };
 
-    let t = match_expr(buf, names, call_stack, scope_stack, tok)?;
-    let cond = match t {
-        Object::Bool(b) => b,
-        _ => {output::error(buf, format!("Conditional expression must have type `bool`, not `{:?}`", t))?; false}
-    };
-    
-    if !cond {eat_expr(buf)?}
-    else {
-        tok = get_tok(buf)?;
-        res = match_expr(buf, names, call_stack, scope_stack, tok)?;
+        let tok = get_tok(buf)?;
+        let value = match tok {
+            Token::RBrace | Token::Empty => {output::error(buf, format!("Expected a value after map key, got `{:?}`", tok))?; break},
+            _ => parse_expr(buf, tok)?
+        };
+
+        entries.push((key, value));
     }
 
-    tok = get_tok(buf)?;
+    return Ok(Expr::new(ExprKind::Map(entries), start.to(buf.span())));
+}
+
+fn parse_if_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
+    let cond = parse_next_expr(buf)?;
+    let then = parse_next_expr(buf)?;
+
+    let tok = get_tok(buf)?;
     match tok {
         Token::Else => (),
         _ => output::error(buf, format!("Expected `else` after `if` expression, got `{:?}`", tok))?
     }
 
-    if cond {eat_expr(buf)?}
-    else {
-        tok = get_tok(buf)?;
-        res = match_expr(buf, names, call_stack, scope_stack, tok)?;
+    let otherwise = parse_next_expr(buf)?;
+
+    return Ok(ExprKind::If(Box::new(cond), Box::new(then), Box::new(otherwise)));
+}
+
+fn parse_do_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
+    let mut exprs = Vec::<Expr>::new();
+
+    loop {
+        let tok = get_tok(buf)?;
+        match tok {
+            // The closing `)` is left for `parse_paren_expr`
+            Token::RParen => {buf.index = buf.tok_start; break},
+            Token::Empty => {output::error(buf, "Expected `)`, got end of input".to_owned())?; break},
+            _ => exprs.push(parse_expr(buf, tok)?)
+        }
     }
 
-    return Ok(res);
+    if exprs.is_empty() {output::error(buf, "Expected at least one expression in `do`".to_owned())?}
+
+    return Ok(ExprKind::Do(exprs));
 }
 
-fn parse_let_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>) -> Result<Object, Box<dyn Error>> {
+fn parse_let_expr(buf: &mut Buffer, kind: LetKind) -> Result<ExprKind, Box<dyn Error>> {
     let mut tok = get_tok(buf)?;
     match tok {
         Token::LParen => (),
         _ => output::error(buf, format!("Expected `(`, got `{:?}`", tok))?
     }
 
-    scope_stack.push(vec![]);
-    let len = scope_stack.len();
+    let mut bindings = Vec::<Binding>::new();
 
     loop {
         tok = get_tok(buf)?;
-        match tok {
-            Token::LParen => (),
+        let start = buf.span();
+        // A list pattern can be written without the parentheses around the binding, as in `(let ([a b] ls) ...)`
+        let parenthesized = match tok {
+            Token::LParen => true,
+            Token::LBracket => false,
             _ => break
-        }
+        };
 
-        tok = get_tok(buf)?;
-        let name = match tok {
-            Token::Name(s) => s,
-            _ => {output::error(buf, format!("Expected name, got `{:?}`", tok))?; String::new()}
+        if parenthesized {tok = get_tok(buf)?;}
+        let pattern = match matchexpr::parse_binding_pattern(buf, tok)? {
+            Some(pattern) => pattern,
+            None => {output::error(buf, "Expected name or pattern".to_owned())?; Pattern::Wildcard}
         };
-        tok = get_tok(buf)?;
-        let val = match_expr(buf, names, call_stack, scope_stack, tok)?;
-        
-        if names.contains_key(&name) {output::error(buf, format!("Name `{}` already exists", name))?}
-        scope_stack[len - 1].push(name.clone());
-        names.insert(name, val);
+        let val = parse_next_expr(buf)?;
 
-        tok = get_tok(buf)?;
-        match tok {
-            Token::RParen => (),
-            _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
+        if parenthesized {expect_rparen(buf)?;}
+        let span = start.to(buf.span());
+
+        // Functions don't look at the names they refer to until they're called, so they can be bound before those names are
+        if kind == LetKind::Recursive && !(matches!(pattern, Pattern::Bind(_)) && matches!(val.kind, ExprKind::Lambda(..))) {
+            output::error_at(buf, span, ErrorKind::Syntax, "`letrec` can only bind names to lambdas".to_owned())?
+        }
+        // Only `let*` bindings can shadow each other, since the others are all bound at once
+        if kind != LetKind::Sequential {
+            let bound: Vec<&str> = bindings.iter().flat_map(|b| b.pattern.names()).collect();
+            if let Some(name) = pattern.names().into_iter().find(|n| bound.contains(n)) {
+                output::error_at(buf, span, ErrorKind::Name, format!("Name `{}` is bound more than once in `{}`", name, kind.keyword()))?
+            }
         }
+
+        bindings.push(Binding {
+            pattern,
+            val,
+            span
+        });
     }
 
     match tok {
@@ -262,55 +288,7 @@ fn parse_let_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>,
         _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
     }
 
-    tok = get_tok(buf)?;
-    let res = match_expr(buf, names, call_stack, scope_stack, tok)?;
+    let body = parse_next_expr(buf)?;
 
-    for name in &scope_stack[len - 1] {
-        names.remove(name);
-    }
-    scope_stack.pop().unwrap();
-
-    return Ok(res);
-}
-
-fn parse_call_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>, func: &Func) -> Result<Object, Box<dyn Error>> {
-    let mut args = Vec::<Arg>::new();
-
-    for i in 0..func.params.len() {
-        let tok = get_tok(buf)?;
-        if let Token::RParen = tok {
-            output::error(buf, format!(
-                "Function `{}` takes {} arguments, got {}",
-                func.name,
-                func.params.len(),
-                i
-            ))?
-        }
-
-        let val = match_expr(buf, names, call_stack, scope_stack, tok)?;
-        let arg = Arg {
-            val: val.clone(),
-            arg_type: match val {
-                Object::Function(_) => Type::Function,
-                Object::Int(_) => Type::Int,
-                Object::Bool(_) => Type::Bool,
-                Object::Str(_) => Type::Str,
-                Object::List(_) => Type::List,
-                Object::None => Type::None
-            }
-        };
-        
-        if arg.arg_type == func.params[i].arg_type || func.params[i].arg_type == Type::Any {
-            args.push(arg);
-        }
-        else {output::error(buf, format!(
-            "Parameter `{}` of function `{}` expects type `{:?}`, got type `{:?}`",
-            func.params[i].name,
-            func.name,
-            func.params[i].arg_type,
-            arg.arg_type
-        ))?}
-    }
-    
-    return Ok(call_function(buf, names, call_stack, scope_stack, func, &args)?);
+    return Ok(ExprKind::Let(kind, bindings, Box::new(body)));
 }
@@ -1,25 +1,73 @@
 use super::*;
+use super::matchexpr::parse_binding_pattern;
 use crate::{
     object::{
         Type,
-        Object
+        Object,
+        StructDef,
+        EnumDef,
+        RecordType
     },
-    call::{
-        Param,
-        Func
-    }
+    error::ErrorKind,
+    call::Param
+};
+
+use std::{
+    mem::replace,
+    rc::Rc
 };
 
-fn parse_param_list<'a>(buf: &'a mut Buffer, tok: &mut Token) -> Result<Vec<Param>, Box<dyn Error>> {
+/// Parses parameters, leaving the token after them in `tok`. If `patterns` is
+/// given, the parameters are a function's, which can also be patterns that are
+/// added to it as `let` bindings destructuring the argument, or end with `& rest`.
+fn parse_param_list(buf: &mut Buffer, tok: &mut Token, mut patterns: Option<&mut Vec<Binding>>) -> Result<Vec<Param>, Box<dyn Error>> {
     let mut params = Vec::<Param>::new();
+    // Every name the parameters bind, including the ones inside patterns
+    let mut bound = Vec::<String>::new();
     let mut idx: usize;
     loop {
-        let name: String;
         let mut arg_type = Type::Any;
+        let mut rest = false;
 
         *tok = get_tok(buf)?;
-        if let Token::Name(s) = tok {name = s.clone()}
-        else {break}
+        let start = buf.span();
+        if let Some(Param {name, rest: true, ..}) = params.last() {
+            if !matches!(tok, Token::RParen) {output::error(buf, format!("Rest parameter `{}` has to come last", name))?}
+        }
+
+        let name = match (&*tok, &mut patterns) {
+            (Token::Name(s), _) => {
+                check_bound(buf, &mut bound, vec![s], start)?;
+                s.clone()
+            },
+            (Token::Op(op::Op::And), Some(_)) => {
+                rest = true;
+                *tok = get_tok(buf)?;
+                match &*tok {
+                    Token::Name(s) => {
+                        check_bound(buf, &mut bound, vec![s], buf.span())?;
+                        s.clone()
+                    },
+                    _ => {output::error(buf, format!("Expected name after `&`, got `{:?}`", tok))?; String::new()}
+                }
+            },
+            (Token::LParen | Token::LBracket, Some(patterns)) => {
+                let pattern = parse_binding_pattern(buf, replace(tok, Token::Empty))?.unwrap_or(Pattern::Wildcard);
+                let span = start.to(buf.span());
+                check_bound(buf, &mut bound, pattern.names(), span)?;
+
+                // The argument is bound to the pattern as written, which can't clash with a real name
+                let name = pattern.to_string();
+                patterns.push(Binding {
+                    pattern,
+                    val: Expr::new(ExprKind::Name(name.clone()), span),
+                    span
+                });
+
+                name
+            },
+            _ => break
+        };
 
         idx = buf.index;
         *tok = get_tok(buf)?;
@@ -27,92 +75,280 @@ fn parse_param_list<'a>(buf: &'a mut Buffer, tok: &mut Token) -> Result<Vec<Para
         match tok {
             Token::Colon => {
                 *tok = get_tok(buf)?;
-
-                if let Token::Typename(t) = tok {arg_type = t.clone()}
-                else {output::error(buf, "Expected type after `:`".to_owned())?}
+                arg_type = parse_type(buf, replace(tok, Token::Empty))?;
             },
             _ => buf.index = idx
         }
 
+        let default = parse_default(buf, &name, &arg_type)?;
+        if default.is_none() && !rest {
+            if let Some(Param {default: Some(_), name: prev, ..}) = params.last() {
+                output::error_at(buf, start, ErrorKind::Syntax, format!("Parameter `{}` needs a default value, like `{}` before it", name, prev))?
+            }
+        }
+
         params.push(Param {
             name,
-            arg_type
+            arg_type,
+            rest,
+            default
         });
     }
 
     return Ok(params);
 }
 
-pub fn parse_def_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>) -> Result<Object, Box<dyn Error>> {
+/// Adds the names bound by a parameter to `bound`, reporting any that are already in it
+fn check_bound(buf: &Buffer, bound: &mut Vec<String>, names: Vec<&str>, span: Span) -> Result<(), Box<dyn Error>> {
+    if let Some(name) = names.iter().find(|n| bound.iter().any(|b| b == *n)) {
+        output::error_at(buf, span, ErrorKind::Name, format!("Name `{}` is bound more than once in parameters", name))?
+    }
+    bound.extend(names.into_iter().map(str::to_owned));
+
+    return Ok(());
+}
+
+/// Parses the type starting with `tok`, which can be a union like `int | none`
+fn parse_type(buf: &mut Buffer, tok: Token) -> Result<Type, Box<dyn Error>> {
+    let mut types = vec![parse_single_type(buf, tok)?];
+
+    loop {
+        let idx = buf.index;
+        match get_tok(buf)? {
+            Token::Op(op::Op::Or) => {
+                let tok = get_tok(buf)?;
+                types.push(parse_single_type(buf, tok)?);
+            },
+            _ => {buf.index = idx; break}
+        }
+    }
+
+    return Ok(Type::union(types));
+}
+
+/// Parses a type that isn't a union
+fn parse_single_type(buf: &mut Buffer, tok: Token) -> Result<Type, Box<dyn Error>> {
+    Ok(match tok {
+        // `?t` is short for `t | none`
+        Token::Unknown(s) if s == "?" => {
+            let tok = get_tok(buf)?;
+            Type::union(vec![parse_single_type(buf, tok)?, Type::None])
+        },
+        // The item type has to follow `list` directly, since `list [a b]` could be a parameter followed by a pattern
+        Token::Typename(Type::List) if buf.index < buf.len && buf.bytes[buf.index] == b'[' => {
+            get_tok(buf)?;
+            let tok = get_tok(buf)?;
+            let item = parse_type(buf, tok)?;
+
+            let tok = get_tok(buf)?;
+            match tok {
+                Token::RBracket => (),
+                _ => output::error(buf, format!("Expected `]`, got `{:?}`", tok))?
+            }
+
+            Type::ListOf(Box::new(item))
+        },
+        Token::Typename(t) => t,
+        Token::None => Type::None,
+        Token::LParen => parse_fn_type(buf)?,
+        // `map` and `any` are only typenames here, so functions can still be called `map`
+        Token::Name(s) if s == "map" => Type::Map,
+        Token::Name(s) if s == "any" => Type::Any,
+        // Any other name is a `struct` or `enum`, which is looked up once the whole expression is parsed
+        Token::Name(s) => Type::Named(s.into(), buf.span()),
+        _ => {output::error(buf, format!("Expected type, got `{:?}`", tok))?; Type::Any}
+    })
+}
+
+/// Parses a function type like `(fn int int -> bool)` after its `(`
+fn parse_fn_type(buf: &mut Buffer) -> Result<Type, Box<dyn Error>> {
+    let tok = get_tok(buf)?;
+    match &tok {
+        Token::Name(s) if s == "fn" => (),
+        _ => output::error(buf, format!("Expected `fn`, got `{:?}`", tok))?
+    }
+
+    let mut params = Vec::<Type>::new();
+    let mut ret = Type::Any;
+    loop {
+        let tok = get_tok(buf)?;
+        match tok {
+            Token::RParen => break,
+            Token::Empty => {output::error(buf, "Expected `)`, got end of input".to_owned())?; break},
+            // The return type comes last
+            Token::Arrow => {
+                let tok = get_tok(buf)?;
+                ret = parse_type(buf, tok)?;
+                expect_rparen(buf)?;
+                break;
+            },
+            _ => params.push(parse_type(buf, tok)?)
+        }
+    }
+
+    return Ok(Type::Fn(params, Box::new(ret)));
+}
+
+/// Parses the `-> type` after a function's parameters if there is one
+fn parse_return_type(buf: &mut Buffer) -> Result<Type, Box<dyn Error>> {
+    let idx = buf.index;
+    if let Token::Arrow = get_tok(buf)? {
+        let tok = get_tok(buf)?;
+        return parse_type(buf, tok);
+    }
+    buf.index = idx;
+
+    return Ok(Type::Any);
+}
+
+/// Parses the `=value` after a parameter if there is one, checking that the value has the parameter's type
+fn parse_default(buf: &mut Buffer, name: &str, arg_type: &Type) -> Result<Option<Object>, Box<dyn Error>> {
+    let idx = buf.index;
+    match get_tok(buf)? {
+        Token::Op(op::Op::Eq) => (),
+        _ => {buf.index = idx; return Ok(None)}
+    }
+
+    let tok = get_tok(buf)?;
+    let default = match tok {
+        Token::Num(i) => Object::Int(i),
+        Token::Float(f) => Object::Float(f),
+        Token::BigNum(n) => Object::from(n),
+        Token::Str(s) => Object::Str(s),
+        Token::True => Object::Bool(true),
+        Token::False => Object::Bool(false),
+        Token::None => Object::None,
+        _ => {output::error(buf, format!("Expected a literal default value, got `{:?}`", tok))?; Object::None}
+    };
+
+    if !arg_type.matches(&default) {
+        let msg = format!("Default value of parameter `{}` has type `{:?}`, but the parameter has type `{:?}`", name, default.describe_type(), arg_type);
+        output::error_at(buf, buf.span(), ErrorKind::Type, msg)?
+    }
+
+    return Ok(Some(default));
+}
+
+pub fn parse_def_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
     let mut tok = get_tok(buf)?;
     match tok {
         Token::LParen => (),
         _ => output::error(buf, format!("Expected `(`, got `{:?}`", tok))?
     }
-    
+
     tok = get_tok(buf)?;
     let name = match &tok {
         Token::Name(s) => s.clone(),
-        _ => {output::error(buf, format!("Expected name, got `{:?}`", tok))?; panic!("")}
+        _ => {output::error(buf, format!("Expected name, got `{:?}`", tok))?; String::new()}
     };
 
-    let params = parse_param_list(buf, &mut tok)?;
+    let mut patterns = Vec::<Binding>::new();
+    let params = parse_param_list(buf, &mut tok, Some(&mut patterns))?;
 
     match tok {
         Token::RParen => (),
         _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
     }
 
-    let addr = buf.index;
-
-    eat_expr(buf)?;
+    let ret_type = parse_return_type(buf)?;
+    let body = destructure_params(parse_next_expr(buf)?, patterns);
 
-    names.insert(name.clone(), Object::Function(Func {
-        addr,
-        name: name.clone(),
-        params
-    }));
-
-    return Ok(Object::None);
+    return Ok(ExprKind::Def(name, params, ret_type, Rc::new(body)));
 }
 
-pub fn parse_lambda_expr<'a>(buf: &'a mut Buffer) -> Result<Object, Box<dyn Error>> {
+pub fn parse_lambda_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
     let mut tok = get_tok(buf)?;
     match tok {
         Token::LParen => (),
         _ => output::error(buf, format!("Expected `(`, got `{:?}`", tok))?
     }
 
-    let params = parse_param_list(buf, &mut tok)?;
+    let mut patterns = Vec::<Binding>::new();
+    let params = parse_param_list(buf, &mut tok, Some(&mut patterns))?;
 
     match tok {
         Token::RParen => (),
         _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
     }
 
-    let addr = buf.index;
+    let ret_type = parse_return_type(buf)?;
+    let body = destructure_params(parse_next_expr(buf)?, patterns);
+
+    return Ok(ExprKind::Lambda(params, ret_type, Rc::new(body)));
+}
 
-    let mut lparens: usize = 0;
+/// Wraps a function body in a `let` that destructures its pattern parameters
+fn destructure_params(body: Expr, patterns: Vec<Binding>) -> Expr {
+    if patterns.is_empty() {return body;}
 
-    tok = get_tok(buf)?;
+    let span = body.span;
+
+    return Expr::new(ExprKind::Let(LetKind::Sequential, patterns, Box::new(body)), span);
+}
+
+pub fn parse_struct_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
+    let mut tok = get_tok(buf)?;
+    let name = match &tok {
+        Token::Name(s) if !s.contains('.') => s.clone(),
+        _ => {output::error(buf, format!("Expected struct name, got `{:?}`", tok))?; String::new()}
+    };
+
+    let fields = parse_param_list(buf, &mut tok, None)?;
+
+    // The closing `)` is left for `parse_paren_expr`
     match tok {
-        Token::LParen => lparens += 1,
-        Token::RParen => lparens -= 1,
-        _ => ()
+        Token::RParen => buf.index = buf.tok_start,
+        _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
     }
 
-    while lparens > 0 {
+    return Ok(ExprKind::Struct(Rc::new(StructDef {
+        ty: RecordType::new(name.into()),
+        fields,
+        parent: None
+    })));
+}
+
+pub fn parse_enum_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
+    let mut tok = get_tok(buf)?;
+    let name = match &tok {
+        Token::Name(s) if !s.contains('.') => s.clone(),
+        _ => {output::error(buf, format!("Expected enum name, got `{:?}`", tok))?; String::new()}
+    };
+
+    // Each variant is either a bare name or `(Name fields...)`
+    let mut variants = Vec::<(String, Vec<Param>)>::new();
+    loop {
         tok = get_tok(buf)?;
         match tok {
-            Token::LParen => lparens += 1,
-            Token::RParen => lparens -= 1,
-            _ => ()
+            Token::Name(s) if !s.contains('.') => variants.push((s, Vec::new())),
+            Token::LParen => {
+                tok = get_tok(buf)?;
+                let variant = match &tok {
+                    Token::Name(s) if !s.contains('.') => s.clone(),
+                    _ => {output::error(buf, format!("Expected variant name, got `{:?}`", tok))?; String::new()}
+                };
+                let fields = parse_param_list(buf, &mut tok, None)?;
+
+                match tok {
+                    Token::RParen => (),
+                    _ => output::error(buf, format!("Expected `)`, got `{:?}`", tok))?
+                }
+                variants.push((variant, fields));
+            },
+            // The closing `)` is left for `parse_paren_expr`
+            Token::RParen => {buf.index = buf.tok_start; break},
+            _ => {output::error(buf, format!("Expected variant, got `{:?}`", tok))?; break}
         }
     }
 
-    return Ok(Object::Function(Func {
-        addr,
-        name: "lambda".to_owned(),
-        params
-    }));
+    let parent = Rc::new(EnumDef {
+        ty: RecordType::new(name.into()),
+        variants: variants.iter().map(|(name, _)| RecordType::new(name.as_str().into())).collect()
+    });
+
+    return Ok(ExprKind::Enum(variants.into_iter().zip(parent.variants.clone()).map(|((_, fields), ty)| Rc::new(StructDef {
+        ty,
+        fields,
+        parent: Some(parent.clone())
+    })).collect()));
 }
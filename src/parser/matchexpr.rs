@@ -0,0 +1,142 @@
+use super::*;
+use crate::{
+    ast::{
+        MatchArm,
+        Pattern
+    },
+    object::{
+        Type,
+        Object
+    },
+    error::ErrorKind,
+    pattern::is_exhaustive
+};
+
+pub fn parse_match_expr(buf: &mut Buffer) -> Result<ExprKind, Box<dyn Error>> {
+    let start = buf.span();
+    let value = parse_next_expr(buf)?;
+    let mut arms = Vec::<MatchArm>::new();
+
+    loop {
+        let tok = get_tok(buf)?;
+        let arm_start = buf.span();
+        match tok {
+            Token::LParen => (),
+            // The closing `)` is left for `parse_paren_expr`
+            Token::RParen => {buf.index = buf.tok_start; break},
+            _ => {output::error(buf, format!("Expected `(`, got `{:?}`", tok))?; break}
+        }
+
+        let tok = get_tok(buf)?;
+        let pattern = parse_pattern(buf, tok)?;
+        check_names(buf, &pattern, arm_start)?;
+
+        let body = parse_next_expr(buf)?;
+        expect_rparen(buf)?;
+
+        arms.push(MatchArm {
+            pattern,
+            body,
+            span: arm_start.to(buf.span())
+        });
+    }
+
+    // Like `if` without `else`, a `match` that could fall through is rejected up front
+    if !is_exhaustive(arms.iter().map(|arm| &arm.pattern)) {
+        output::error_at(buf, start, ErrorKind::Syntax, "`match` doesn't handle every value, add a `_` case".to_owned())?
+    }
+
+    return Ok(ExprKind::Match(Box::new(value), arms));
+}
+
+/// Parses the left side of a `let` binding or a parameter, where a name is always
+/// bound even if it's capitalized. Returns `None` if `tok` doesn't start a pattern.
+pub fn parse_binding_pattern(buf: &mut Buffer, tok: Token) -> Result<Option<Pattern>, Box<dyn Error>> {
+    let start = buf.span();
+    let pattern = match tok {
+        Token::Name(n) if !n.contains('.') => Pattern::Bind(n),
+        Token::LParen | Token::LBracket => parse_pattern(buf, tok)?,
+        _ => return Ok(None)
+    };
+    check_names(buf, &pattern, start.to(buf.span()))?;
+
+    return Ok(Some(pattern));
+}
+
+/// Checks that a pattern doesn't bind the same name twice
+fn check_names(buf: &Buffer, pattern: &Pattern, span: Span) -> Result<(), Box<dyn Error>> {
+    let names = pattern.names();
+    if let Some(name) = names.iter().enumerate().find(|(i, n)| names[..*i].contains(n)).map(|(_, n)| n) {
+        output::error_at(buf, span, ErrorKind::Name, format!("Name `{}` is bound more than once in pattern", name))?
+    }
+
+    return Ok(());
+}
+
+/// Parses the pattern starting with `tok`
+fn parse_pattern(buf: &mut Buffer, tok: Token) -> Result<Pattern, Box<dyn Error>> {
+    Ok(match tok {
+        Token::Name(n) if n == "_" => Pattern::Wildcard,
+        // Capitalized names are variants like `None`, and lowercase names are bound to the value
+        Token::Name(n) if variant_name(&n).starts_with(char::is_uppercase) => Pattern::Record(Type::Named(n.into(), buf.span()), Vec::new()),
+        Token::Name(n) if !n.contains('.') => Pattern::Bind(n),
+        Token::Num(i) => Pattern::Literal(Object::Int(i)),
+        Token::Float(f) => Pattern::Literal(Object::Float(f)),
+        Token::BigNum(n) => Pattern::Literal(Object::from(n)),
+        Token::Str(s) => Pattern::Literal(Object::Str(s)),
+        Token::True => Pattern::Literal(Object::Bool(true)),
+        Token::False => Pattern::Literal(Object::Bool(false)),
+        Token::None => Pattern::Literal(Object::None),
+        Token::LParen => {
+            let tok = get_tok(buf)?;
+            let name = match tok {
+                Token::Name(n) => Type::Named(n.into(), buf.span()),
+                _ => {output::error(buf, format!("Expected constructor name, got `{:?}`", tok))?; Type::Any}
+            };
+
+            let mut fields = Vec::<Pattern>::new();
+            loop {
+                let tok = get_tok(buf)?;
+                match tok {
+                    Token::RParen => break,
+                    Token::Empty => {output::error(buf, "Expected `)`, got end of input".to_owned())?; break},
+                    _ => fields.push(parse_pattern(buf, tok)?)
+                }
+            }
+
+            Pattern::Record(name, fields)
+        },
+        Token::LBracket => {
+            let mut items = Vec::<Pattern>::new();
+            let mut rest = None;
+            loop {
+                let tok = get_tok(buf)?;
+                match tok {
+                    Token::RBracket => break,
+                    Token::Empty => {output::error(buf, "Expected `]`, got end of input".to_owned())?; break},
+                    // `& rest` has to come last
+                    Token::Op(op::Op::And) => {
+                        let tok = get_tok(buf)?;
+                        rest = Some(Box::new(parse_pattern(buf, tok)?));
+
+                        let tok = get_tok(buf)?;
+                        match tok {
+                            Token::RBracket => (),
+                            _ => output::error(buf, format!("Expected `]` after rest pattern, got `{:?}`", tok))?
+                        }
+                        break;
+                    },
+                    _ => items.push(parse_pattern(buf, tok)?)
+                }
+            }
+
+            Pattern::List(items, rest)
+        },
+        _ => {output::error(buf, format!("Expected pattern, got `{:?}`", tok))?; Pattern::Wildcard}
+    })
+}
+
+/// The unqualified part of a possibly qualified name like `geo.Circle`
+fn variant_name(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or_default()
+}
@@ -1,12 +1,14 @@
 use super::*;
 
+use num_traits::Zero;
+
 use crate::{
-    tokenizer::{
-        Buffer,
-        get_tok
-    }
+    tokenizer::Span,
+    object::Object,
+    error::ErrorKind
 };
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Op {
     Add,
     Sub,
@@ -19,168 +21,133 @@ pub enum Op {
     And
 }
 
-pub fn parse_op_expr<'a>(buf: &'a mut Buffer, names: &mut HashMap<String, Object>, call_stack: &mut Vec<CallInfo>, scope_stack: &mut Vec<Vec<String>>, op: Op) -> Result<Object, Box<dyn Error>> {
-    let mut tok: Token;
-    
-    Ok(match op {
-        Op::Add => {
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            match lhs_obj {
-                Object::Int(i) => {
-                    tok = get_tok(buf)?;
+impl Op {
+    /// The operator as it's written in source code
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Eq => "=",
+            Op::Gt => ">",
+            Op::Lt => "<",
+            Op::Or => "|",
+            Op::And => "&"
+        }
+    }
+}
 
-                    let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-                    let rhs = match rhs_obj {
-                        Object::Int(i) => i,
-                        _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; 0}
-                    };
+/// Parses the operands of an operator. Arithmetic and logical operators take
+/// any number of operands past the first two and apply to them from the left, so
+/// `(- 10 2 3)` means `(- (- 10 2) 3)`. Comparisons always take two.
+pub fn parse_op_expr(buf: &mut Buffer, op: Op) -> Result<ExprKind, Box<dyn Error>> {
+    let mut operands = Vec::<Expr>::new();
+    loop {
+        let tok = get_tok(buf)?;
+        match tok {
+            // The closing `)` is left for `parse_paren_expr`
+            Token::RParen => {buf.index = buf.tok_start; break},
+            Token::Empty => {output::error(buf, "Expected `)`, got end of input".to_owned())?; break},
+            _ => operands.push(parse_expr(buf, tok)?)
+        }
+    }
 
-                    Object::Int(i + rhs)
-                },
-                Object::Str(s) => {
-                    tok = get_tok(buf)?;
+    let variadic = !matches!(op, Op::Eq | Op::Lt | Op::Gt);
+    if operands.len() < 2 || (!variadic && operands.len() > 2) {
+        let expected = if variadic {"at least 2"} else {"2"};
+        output::error(buf, format!("`{}` takes {} operands, got {}", op.symbol(), expected, operands.len()))?
+    }
+
+    // Every operand but the last becomes part of the left-hand side
+    let mut operands = operands.into_iter();
+    let mut lhs = operands.next().unwrap();
+    let mut rhs = operands.next().unwrap();
+    let start = lhs.span;
+    for next in operands {
+        let span = start.to(rhs.span);
+        lhs = Expr::new(ExprKind::Op(op, Box::new(lhs), Box::new(rhs)), span);
+        rhs = next;
+    }
 
-                    let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-                    let rhs = match rhs_obj {
-                        Object::Str(s) => s,
-                        _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; String::new()}
-                    };
-                    let mut out = s;
-                    out.push_str(&rhs);
-                    
-                    Object::Str(out)
+    return Ok(ExprKind::Op(op, Box::new(lhs), Box::new(rhs)));
+}
+
+/// Applies `op` to two evaluated operands, reporting type errors at `span`
+pub fn apply_op(buf: &Buffer, span: Span, op: Op, lhs_obj: Object, rhs_obj: Object) -> Result<Object, Box<dyn Error>> {
+    Ok(match op {
+        Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Lt | Op::Gt => match (lhs_obj, rhs_obj) {
+            (Object::Int(lhs), Object::Int(rhs)) => {
+                let res = match op {
+                    Op::Add => lhs.checked_add(rhs),
+                    Op::Sub => lhs.checked_sub(rhs),
+                    Op::Mul => lhs.checked_mul(rhs),
+                    Op::Div => {
+                        if rhs == 0 {output::error_at(buf, span, ErrorKind::Runtime, "Cannot divide by 0".to_owned())?}
+                        lhs.checked_div(rhs)
+                    },
+                    Op::Lt => return Ok(Object::Bool(lhs < rhs)),
+                    _ => return Ok(Object::Bool(lhs > rhs))
+                };
+
+                match res {
+                    Some(i) => Object::Int(i),
+                    None => {
+                        let msg = format!("Integer overflow in `({} {} {})`", op.symbol(), lhs, rhs);
+                        output::error_at(buf, span, ErrorKind::Runtime, msg)?;
+
+                        Object::None
+                    }
                 }
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", lhs_obj))?; Object::None}
+            },
+            (Object::BigInt(lhs), Object::BigInt(rhs)) => match op {
+                Op::Add => Object::from(&*lhs + &*rhs),
+                Op::Sub => Object::from(&*lhs - &*rhs),
+                Op::Mul => Object::from(&*lhs * &*rhs),
+                Op::Div => {
+                    if rhs.is_zero() {output::error_at(buf, span, ErrorKind::Runtime, "Cannot divide by 0".to_owned())?}
+                    Object::from(&*lhs / &*rhs)
+                },
+                Op::Lt => Object::Bool(lhs < rhs),
+                _ => Object::Bool(lhs > rhs)
+            },
+            (Object::Float(lhs), Object::Float(rhs)) => match op {
+                Op::Add => Object::Float(lhs + rhs),
+                Op::Sub => Object::Float(lhs - rhs),
+                Op::Mul => Object::Float(lhs * rhs),
+                Op::Div => Object::Float(lhs / rhs),
+                Op::Lt => Object::Bool(lhs < rhs),
+                _ => Object::Bool(lhs > rhs)
+            },
+            (Object::Str(lhs), Object::Str(rhs)) if op == Op::Add => Object::Str(lhs + &rhs),
+            (lhs, rhs) => {
+                let lhs_valid = match lhs {
+                    Object::Int(_) | Object::BigInt(_) | Object::Float(_) => true,
+                    Object::Str(_) => op == Op::Add,
+                    _ => false
+                };
+
+                // Operands are never converted, so the right operand must match the left one
+                let msg = if lhs_valid {format!("Expected {:?}, got `{:?}`", lhs.get_type(), rhs)}
+                    else if op == Op::Add {format!("Expected int, bigint, float or string, got `{:?}`", lhs)}
+                    else {format!("Expected int, bigint or float, got `{:?}`", lhs)};
+                output::error_at(buf, span, ErrorKind::Type, msg)?;
+
+                Object::None
             }
         },
-        Op::Sub => Object::Int({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let lhs = match lhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", lhs_obj))?; 0}
-            };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let rhs = match rhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; 0}
-            };
-
-            lhs - rhs
-        }),
-        Op::Mul => Object::Int({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let lhs = match lhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", lhs_obj))?; 0}
-            };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let rhs = match rhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; 0}
-            };
-
-            lhs * rhs
-        }),
-        Op::Div => Object::Int({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let lhs = match lhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", lhs_obj))?; 0}
-            };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let rhs = match rhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; 0}
-            };
-
-            if rhs == 0 {output::error(buf, "Cannot divide by 0".to_owned())?}
-            lhs / rhs
-        }),
-        Op::Eq => Object::Bool({
-            tok = get_tok(buf)?;
-            let lhs = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            tok = get_tok(buf)?;
-            let rhs = match_expr(buf, names, call_stack, scope_stack, tok)?;
-
-            lhs == rhs
-        }),
-        Op::Lt => Object::Bool({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let lhs = match lhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", lhs_obj))?; 0}
-            };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let rhs = match rhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; 0}
-            };
-
-            lhs < rhs
-        }),
-        Op::Gt => Object::Bool({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let lhs = match lhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", lhs_obj))?; 0}
-            };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let rhs = match rhs_obj {
-                Object::Int(i) => i,
-                _ => {output::error(buf, format!("Expected int, got `{:?}`", rhs_obj))?; 0}
-            };
-
-            lhs > rhs
-        }),
-        Op::Or => Object::Bool({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
+        Op::Eq => Object::Bool(lhs_obj == rhs_obj),
+        Op::Or | Op::And => {
             let lhs = match lhs_obj {
                 Object::Bool(b) => b,
-                _ => {output::error(buf, format!("Expected bool, got `{:?}`", lhs_obj))?; false}
+                _ => {output::error_at(buf, span, ErrorKind::Type, format!("Expected bool, got `{:?}`", lhs_obj))?; false}
             };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let rhs = match rhs_obj {
-                Object::Bool(b) => b,
-                _ => {output::error(buf, format!("Expected bool, got `{:?}`", rhs_obj))?; false}
-            };
-
-            lhs || rhs
-        }),
-        Op::And => Object::Bool({
-            tok = get_tok(buf)?;
-            let lhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
-            let lhs = match lhs_obj {
-                Object::Bool(b) => b,
-                _ => {output::error(buf, format!("Expected bool, got `{:?}`", lhs_obj))?; false}
-            };
-
-            tok = get_tok(buf)?;
-            let rhs_obj = match_expr(buf, names, call_stack, scope_stack, tok)?;
             let rhs = match rhs_obj {
                 Object::Bool(b) => b,
-                _ => {output::error(buf, format!("Expected bool, got `{:?}`", rhs_obj))?; false}
+                _ => {output::error_at(buf, span, ErrorKind::Type, format!("Expected bool, got `{:?}`", rhs_obj))?; false}
             };
 
-            lhs && rhs
-        })
+            Object::Bool(if op == Op::Or {lhs || rhs} else {lhs && rhs})
+        }
     })
 }
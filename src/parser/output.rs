@@ -1,59 +1,87 @@
 use super::*;
-use crate::object::Object;
+use crate::{
+    tokenizer::Span,
+    object::Object,
+    error::{
+        ErrorKind,
+        SputterError
+    }
+};
 
 pub fn repr_object(obj: &Object, buf: &mut String) {
     match obj {
         Object::Int(i) => buf.push_str(i.to_string().as_str()),
+        Object::BigInt(n) => buf.push_str(n.to_string().as_str()),
+        // Debug formatting keeps the decimal point on whole numbers, so floats don't look like ints
+        Object::Float(f) => buf.push_str(format!("{:?}", f).as_str()),
         Object::Str(s) => buf.push_str(s.as_str()),
         Object::Bool(b) => buf.push_str(format!("{}", b).as_str()),
         Object::Function(f) => buf.push_str(format!("{:?}", f).as_str()),
         Object::List(l) => {
-            buf.push_str("[");
-            for item in l {
+            buf.push('[');
+            for item in l.iter() {
                 repr_object(item, buf);
-                buf.push_str(" ");
+                buf.push(' ');
+            }
+            if !l.is_empty() {buf.pop();}
+            buf.push(']');
+        },
+        Object::Map(m) => {
+            buf.push('{');
+            for (key, value) in m.iter() {
+                repr_object(key, buf);
+                buf.push(' ');
+                repr_object(value, buf);
+                buf.push(' ');
+            }
+            if !m.is_empty() {buf.pop();}
+            buf.push('}');
+        },
+        Object::Record(r) => {
+            buf.push_str(&r.def.ty.name);
+            if r.values.is_empty() {return;}
+
+            buf.push_str(" {");
+            for (name, value) in r.fields() {
+                buf.push_str(name);
+                buf.push(' ');
+                repr_object(value, buf);
+                buf.push(' ');
             }
             buf.pop();
-            buf.push_str("]");
+            buf.push('}');
         },
-        Object::None => buf.push_str("none")
+        Object::None => buf.push_str("none"),
+        Object::Module(m) => buf.push_str(format!("{:?}", m).as_str())
     }
 }
 
-pub fn error(buf: &Buffer, msg: String) -> Result<(), Box<dyn Error>> {
-    let line: String;
-    let mut line_start = 0usize;
-    let mut line_end = buf.len;
-    let mut line_num = 1;
-    let mut carat = String::new();
-    
-    for i in 0..buf.len {
-        if buf.bytes[i] == b'\n' {
-            if i < buf.index {
-                line_start = i + 1;
-                line_num += 1;
-            }
-
-            if i >= buf.index && i > line_start {line_end = i};
-        }
+/// Shows a value in an error message the way it's written in source code, with
+/// strings quoted so they stand apart from the message
+pub fn repr_value(obj: &Object) -> String {
+    let mut repr = String::new();
+    match obj {
+        Object::Str(s) => repr = format!("{:?}", s),
+        _ => repr_object(obj, &mut repr)
     }
 
-    line = String::from_utf8(buf.bytes[line_start..line_end].to_vec())?;
-    for i in 0..line.len() {
-        if i == buf.index - line_start - 1 {
-            carat.push('^');
-            break;
-        }
-        carat.push(' ');
-    }
+    return repr;
+}
+
+/// Raises a syntax error at the most recently lexed token
+pub fn error(buf: &Buffer, msg: String) -> Result<(), Box<dyn Error>> {
+    return error_at(buf, buf.span(), ErrorKind::Syntax, msg);
+}
 
-    println!(
-        "\u{001b}[31mError at line {}\u{001b}[0m: {}\n{}\n\u{001b}[31m{}\u{001b}[0m",
-        line_num,
-        msg,
-        line,
-        carat
-    );
+/// Raises an error at the code covered by `span`
+pub fn error_at(buf: &Buffer, span: Span, kind: ErrorKind, msg: String) -> Result<(), Box<dyn Error>> {
+    return Err(Box::new(SputterError::at(buf, span, kind, msg)));
+}
 
-    exit(1);
+/// Prints an error to stderr, using color for errors raised by Sputter code if `color` is set
+pub fn report(err: &(dyn Error + 'static), color: bool) {
+    match err.downcast_ref::<SputterError>() {
+        Some(e) => eprintln!("{}", e.render(color)),
+        None => eprintln!("Error: {}", err)
+    }
 }
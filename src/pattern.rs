@@ -0,0 +1,180 @@
+use crate::{
+    tokenizer::{
+        Buffer,
+        Span
+    },
+    ast::Pattern,
+    object::{
+        Type,
+        Object,
+        RecordType
+    },
+    error::ErrorKind,
+    parser::output
+};
+
+use std::{
+    error::Error,
+    fmt::{
+        Display,
+        Formatter
+    }
+};
+
+impl Pattern {
+    /// The names the pattern binds, in the order their values are bound
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+
+        return names;
+    }
+
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Pattern::Bind(name) => names.push(name),
+            Pattern::Record(_, fields) => for field in fields {field.collect_names(names)},
+            Pattern::List(items, rest) => {
+                for item in items {item.collect_names(names);}
+                if let Some(rest) = rest {rest.collect_names(names);}
+            },
+            Pattern::Wildcard | Pattern::Literal(_) => ()
+        }
+    }
+
+    /// Whether the pattern matches every value of the type it expects. A record
+    /// pattern only counts if every value of its fields matches.
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Bind(_) => true,
+            Pattern::Record(_, fields) => fields.iter().all(Pattern::is_irrefutable),
+            Pattern::Literal(_) | Pattern::List(..) => false
+        }
+    }
+
+    /// Checks whether `obj` matches the pattern, adding the values of its names to `bound`
+    fn matches(&self, buf: &Buffer, obj: &Object, bound: &mut Vec<Object>, span: Span) -> Result<bool, Box<dyn Error>> {
+        return Ok(match (self, obj) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Bind(_), _) => {
+                bound.push(obj.clone());
+                true
+            },
+            (Pattern::Literal(lit), _) => lit == obj,
+            (Pattern::Record(Type::Struct(ty), fields), Object::Record(r)) if r.def.ty == *ty => {
+                if fields.len() != r.values.len() {
+                    let msg = format!("Pattern for `{}` has {} fields, but `{}` has {}", ty.name, fields.len(), ty.name, r.values.len());
+                    output::error_at(buf, span, ErrorKind::Type, msg)?;
+                }
+
+                for (field, value) in fields.iter().zip(r.values.iter()) {
+                    if !field.matches(buf, value, bound, span)? {return Ok(false);}
+                }
+                true
+            },
+            (Pattern::List(items, rest), Object::List(ls)) => {
+                let fits = if rest.is_some() {ls.len() >= items.len()} else {ls.len() == items.len()};
+                if !fits {return Ok(false);}
+
+                for (item, value) in items.iter().zip(ls.iter()) {
+                    if !item.matches(buf, value, bound, span)? {return Ok(false);}
+                }
+                match rest {
+                    Some(rest) => rest.matches(buf, &Object::List(ls[items.len()..].into()), bound, span)?,
+                    None => true
+                }
+            },
+            _ => false
+        });
+    }
+}
+
+/// Shows a pattern the way it's written in source code
+impl Display for Pattern {
+    fn fmt(&self, form: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Pattern::Wildcard => write!(form, "_"),
+            Pattern::Bind(name) => write!(form, "{}", name),
+            Pattern::Literal(Object::Str(s)) => write!(form, "{:?}", s),
+            Pattern::Literal(obj) => {
+                let mut repr = String::new();
+                output::repr_object(obj, &mut repr);
+                write!(form, "{}", repr)
+            },
+            Pattern::Record(name, fields) if fields.is_empty() => write!(form, "{:?}", name),
+            Pattern::Record(name, fields) => {
+                write!(form, "({:?}", name)?;
+                for field in fields {write!(form, " {}", field)?;}
+                write!(form, ")")
+            },
+            Pattern::List(items, rest) => {
+                let items: Vec<String> = items.iter().map(Pattern::to_string).collect();
+                write!(form, "[{}", items.join(" "))?;
+                match rest {
+                    Some(rest) if items.is_empty() => write!(form, "& {}]", rest),
+                    Some(rest) => write!(form, " & {}]", rest),
+                    None => write!(form, "]")
+                }
+            }
+        }
+    }
+}
+
+/// Matches `obj` against the pattern of a `let` binding or parameter, returning
+/// the values bound to its names
+pub fn destructure(buf: &Buffer, pattern: &Pattern, obj: &Object, span: Span) -> Result<Vec<Object>, Box<dyn Error>> {
+    let mut bound = Vec::new();
+    if !pattern.matches(buf, obj, &mut bound, span)? {
+        output::error_at(buf, span, ErrorKind::Runtime, format!("`{}` doesn't match the pattern `{}`", output::repr_value(obj), pattern))?
+    }
+
+    return Ok(bound);
+}
+
+/// Checks whether a `match` with these patterns handles every value. Matches
+/// on records are assumed to be exhaustive here, since it isn't known yet
+/// which are variants: `struct`s are checked once the names are resolved, and
+/// enums by `select` once the enum being matched is known.
+pub fn is_exhaustive<'a, I: Iterator<Item = &'a Pattern> + Clone>(patterns: I) -> bool {
+    let has = |lit: &Object| patterns.clone().any(|p| matches!(p, Pattern::Literal(l) if l == lit));
+    let has_list = |rest: bool| patterns.clone().any(|p| match p {
+        Pattern::List(items, None) => !rest && items.is_empty(),
+        Pattern::List(items, Some(tail)) => rest && items.iter().all(Pattern::is_irrefutable) && tail.is_irrefutable(),
+        _ => false
+    });
+
+    return patterns.clone().any(|p| p.is_irrefutable())
+        || (has(&Object::Bool(true)) && has(&Object::Bool(false)))
+        || (has_list(false) && has_list(true))
+        || (patterns.clone().next().is_some() && patterns.clone().all(|p| matches!(p, Pattern::Record(..))));
+}
+
+/// Finds the first of a `match` expression's patterns that `obj` matches,
+/// returning its index and the values bound to its names.
+///
+/// Matching a variant of an enum is an error unless every variant is handled,
+/// even if `obj` itself is.
+pub fn select<'a, I: Iterator<Item = &'a Pattern> + Clone>(buf: &Buffer, patterns: I, obj: &Object, span: Span) -> Result<(usize, Vec<Object>), Box<dyn Error>> {
+    if let Object::Record(r) = obj {
+        if let Some(parent) = &r.def.parent {
+            let handles = |variant: &RecordType| patterns.clone().any(|p| match p {
+                Pattern::Record(Type::Struct(ty), _) => ty == variant && p.is_irrefutable(),
+                _ => p.is_irrefutable()
+            });
+
+            if let Some(missing) = parent.variants.iter().find(|v| !handles(v)) {
+                let msg = format!("`match` doesn't handle variant `{}` of enum `{}`", missing.name, parent.ty.name);
+                output::error_at(buf, span, ErrorKind::Runtime, msg)?;
+            }
+        }
+    }
+
+    for (i, pattern) in patterns.enumerate() {
+        let mut bound = Vec::new();
+        if pattern.matches(buf, obj, &mut bound, span)? {return Ok((i, bound));}
+    }
+
+    output::error_at(buf, span, ErrorKind::Runtime, format!("No pattern in `match` matches `{}`", output::repr_value(obj)))?;
+
+    return Ok((0, Vec::new()));
+}
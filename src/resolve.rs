@@ -0,0 +1,192 @@
+use crate::{
+    tokenizer::{
+        Buffer,
+        Span
+    },
+    ast::{
+        Expr,
+        ExprKind,
+        MatchArm,
+        Pattern
+    },
+    object::{
+        Type,
+        TypeDef,
+        RecordType
+    },
+    call::Param,
+    error::ErrorKind,
+    module::{
+        ModuleId,
+        Modules
+    },
+    parser::output
+};
+
+use std::{
+    error::Error,
+    rc::Rc
+};
+
+/// Defines the types declared by a top-level expression, then replaces every
+/// type name and record pattern in it with the definition it refers to.
+///
+/// Types are declared before anything is resolved, so a `struct` or `enum` can
+/// refer to itself and to the others defined in the same expression.
+pub fn resolve_types(buf: &Buffer, modules: &mut Modules, module: ModuleId, expr: &mut Expr) -> Result<(), Box<dyn Error>> {
+    declare(modules, module, expr);
+
+    return Resolver {buf, modules, module}.expr(expr);
+}
+
+fn declare(modules: &mut Modules, module: ModuleId, expr: &mut Expr) {
+    match &expr.kind {
+        ExprKind::Struct(def) => modules.define_type(module, def.ty.name.to_string(), TypeDef::Struct(def.ty.clone())),
+        ExprKind::Enum(variants) => for def in variants {
+            if let Some(parent) = &def.parent {
+                modules.define_type(module, parent.ty.name.to_string(), TypeDef::Enum(parent.ty.clone()));
+                modules.define_type(module, def.ty.name.to_string(), TypeDef::Variant(def.ty.clone(), parent.ty.clone()));
+            }
+        },
+        _ => ()
+    }
+
+    for child in children(&mut expr.kind) {
+        declare(modules, module, child);
+    }
+}
+
+/// The expressions directly inside an expression
+fn children(kind: &mut ExprKind) -> Vec<&mut Expr> {
+    match kind {
+        ExprKind::Member(obj, _) => vec![obj],
+        ExprKind::List(items) | ExprKind::Do(items) => items.iter_mut().collect(),
+        ExprKind::Map(entries) => entries.iter_mut().flat_map(|(k, v)| vec![k, v]).collect(),
+        ExprKind::Call(head, args, keywords) => std::iter::once(&mut **head)
+            .chain(args.iter_mut())
+            .chain(keywords.iter_mut().map(|(_, arg)| arg))
+            .collect(),
+        ExprKind::Op(_, lhs, rhs) => vec![lhs, rhs],
+        ExprKind::If(cond, then, otherwise) => vec![cond, then, otherwise],
+        ExprKind::Match(value, arms) => std::iter::once(&mut **value).chain(arms.iter_mut().map(|arm| &mut arm.body)).collect(),
+        ExprKind::Let(_, bindings, body) => bindings.iter_mut().map(|b| &mut b.val).chain(std::iter::once(&mut **body)).collect(),
+        // Function bodies are only shared once the function is evaluated
+        ExprKind::Def(_, _, _, body) | ExprKind::Lambda(_, _, body) => Rc::get_mut(body).into_iter().collect(),
+        _ => Vec::new()
+    }
+}
+
+struct Resolver<'a> {
+    buf: &'a Buffer,
+    modules: &'a Modules,
+    module: ModuleId
+}
+
+impl Resolver<'_> {
+    fn expr(&self, expr: &mut Expr) -> Result<(), Box<dyn Error>> {
+        match &mut expr.kind {
+            ExprKind::Def(_, params, ret_type, _) | ExprKind::Lambda(params, ret_type, _) => {
+                self.params(params)?;
+                self.annotation(ret_type)?;
+            },
+            ExprKind::Struct(def) => if let Some(def) = Rc::get_mut(def) {self.params(&mut def.fields)?},
+            ExprKind::Enum(variants) => for def in variants {
+                if let Some(def) = Rc::get_mut(def) {self.params(&mut def.fields)?;}
+            },
+            ExprKind::Let(_, bindings, _) => for binding in bindings {self.pattern(&mut binding.pattern)?;},
+            ExprKind::Match(_, arms) => {
+                let mut structs = Vec::new();
+                for arm in arms.iter_mut() {
+                    if let Some(ty) = self.pattern(&mut arm.pattern)? {structs.push(ty);}
+                }
+                self.check_struct_arms(arms, &structs, expr.span)?;
+            },
+            _ => ()
+        }
+
+        for child in children(&mut expr.kind) {
+            self.expr(child)?;
+        }
+
+        return Ok(());
+    }
+
+    fn params(&self, params: &mut [Param]) -> Result<(), Box<dyn Error>> {
+        for param in params {
+            self.annotation(&mut param.arg_type)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Resolves the names of `struct`s and `enum`s in a type annotation
+    fn annotation(&self, t: &mut Type) -> Result<(), Box<dyn Error>> {
+        match t {
+            Type::Named(name, span) => *t = match self.modules.find_type(self.buf, self.module, name, *span)? {
+                TypeDef::Struct(ty) | TypeDef::Enum(ty) => Type::Struct(ty),
+                TypeDef::Variant(ty, parent) => {
+                    let msg = format!("`{}` is a variant of enum `{}`, not a type", ty.name, parent.name);
+                    output::error_at(self.buf, *span, ErrorKind::Type, msg)?;
+                    Type::Any
+                }
+            },
+            Type::ListOf(item) => self.annotation(item)?,
+            Type::Fn(params, ret) => {
+                for param in params {self.annotation(param)?;}
+                self.annotation(ret)?;
+            },
+            Type::Union(types) => for t in types {self.annotation(t)?},
+            _ => ()
+        }
+
+        return Ok(());
+    }
+
+    /// Resolves the `struct`s and variants that record patterns match, returning the
+    /// type if the pattern is a plain `struct`'s
+    fn pattern(&self, pattern: &mut Pattern) -> Result<Option<RecordType>, Box<dyn Error>> {
+        let mut plain = None;
+        match pattern {
+            Pattern::Record(name, fields) => {
+                if let Type::Named(path, span) = name {
+                    *name = match self.modules.find_type(self.buf, self.module, path, *span)? {
+                        TypeDef::Struct(ty) => {
+                            plain = Some(ty.clone());
+                            Type::Struct(ty)
+                        },
+                        TypeDef::Variant(ty, _) => Type::Struct(ty),
+                        TypeDef::Enum(ty) => {
+                            let msg = format!("`{}` is an enum, match on its variants instead", ty.name);
+                            output::error_at(self.buf, *span, ErrorKind::Type, msg)?;
+                            Type::Any
+                        }
+                    };
+                }
+                for field in fields {self.pattern(field)?;}
+            },
+            Pattern::List(items, rest) => {
+                for item in items {self.pattern(item)?;}
+                if let Some(rest) = rest {self.pattern(rest)?;}
+            },
+            Pattern::Wildcard | Pattern::Bind(_) | Pattern::Literal(_) => ()
+        }
+
+        return Ok(plain);
+    }
+
+    /// Checks that a `match` on `struct`s has a case taking every value of each of
+    /// them. Enums are left to `select`, which checks every variant is handled.
+    fn check_struct_arms(&self, arms: &[MatchArm], structs: &[RecordType], span: Span) -> Result<(), Box<dyn Error>> {
+        let covers = |ty: &RecordType| arms.iter().any(|arm| arm.pattern.is_irrefutable() && match &arm.pattern {
+            Pattern::Record(Type::Struct(t), _) => t == ty,
+            _ => true
+        });
+
+        if let Some(missing) = structs.iter().find(|ty| !covers(ty)) {
+            let msg = format!("`match` doesn't handle every value of struct `{}`, add a `_` case", missing.name);
+            output::error_at(self.buf, span, ErrorKind::Syntax, msg)?;
+        }
+
+        return Ok(());
+    }
+}
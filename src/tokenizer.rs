@@ -1,12 +1,16 @@
 use std::{
     error::Error,
+    num::IntErrorKind,
     fmt::{
         Debug,
         Formatter
     }
 };
 
+use num_bigint::BigInt;
+
 use crate::{
+    ast::LetKind,
     object::Type,
     parser::{
         op::Op,
@@ -24,12 +28,16 @@ pub enum Token {
     LBrace,
     RBrace,
     Colon,
+    /// `->`, which comes before the return type of a function
+    Arrow,
     Name(String),
-    Num(String),
+    Num(i64),
+    Float(f64),
+    BigNum(BigInt),
     Str(String),
     Op(Op),
     Def,
-    Let,
+    Let(LetKind),
     Lambda,
     If,
     Else,
@@ -37,6 +45,10 @@ pub enum Token {
     False,
     None,
     Use,
+    Struct,
+    Enum,
+    Match,
+    Do,
     Typename(Type)
 }
 
@@ -52,22 +64,15 @@ impl Debug for Token {
             Token::LBracket => "[".to_owned(),
             Token::RBracket => "]".to_owned(),
             Token::Colon => ":".to_owned(),
+            Token::Arrow => "->".to_owned(),
             Token::Name(s) => s.clone(),
-            Token::Num(s) => s.clone(),
+            Token::Num(i) => i.to_string(),
+            Token::Float(f) => format!("{:?}", f),
+            Token::BigNum(n) => format!("{}n", n),
             Token::Str(s) => format!("\"{}\"", s),
-            Token::Op(o) => match o {
-                Op::Add => "+",
-                Op::Sub => "-",
-                Op::Mul => "*",
-                Op::Div => "/",
-                Op::Eq => "=",
-                Op::Gt => ">",
-                Op::Lt => "<",
-                Op::Or => "|",
-                Op::And => "&"
-            }.to_owned(),
+            Token::Op(o) => o.symbol().to_owned(),
             Token::Def => "def".to_owned(),
-            Token::Let => "let".to_owned(),
+            Token::Let(kind) => kind.keyword().to_owned(),
             Token::Lambda => "lambda".to_owned(),
             Token::If => "if".to_owned(),
             Token::Else => "else".to_owned(),
@@ -75,23 +80,98 @@ impl Debug for Token {
             Token::False => "false".to_owned(),
             Token::None => "none".to_owned(),
             Token::Use => "use".to_owned(),
-            Token::Typename(t) => (match t {
-                Type::Function => "function",
-                Type::Int => "int",
-                Type::Bool => "bool",
-                Type::Str => "string",
-                Type::List => "list",
-                Type::None => "none_t",
-                Type::Any => "any"
-            }).to_owned()
+            Token::Struct => "struct".to_owned(),
+            Token::Enum => "enum".to_owned(),
+            Token::Match => "match".to_owned(),
+            Token::Do => "do".to_owned(),
+            Token::Typename(t) => format!("{:?}", t)
         })
     }
 }
 
+/// Returns the index of the first byte at or after `i` that isn't a digit or `_`
+fn skip_digits(buf: &Buffer, mut i: usize) -> usize {
+    while i < buf.len && (buf.bytes[i].is_ascii_digit() || buf.bytes[i] == b'_') {i += 1;}
+
+    return i;
+}
+
+/// Lexes an int, bigint or float literal starting at `start`, returning it along with the index after it
+fn lex_number(buf: &mut Buffer, start: usize) -> Result<(Token, usize), Box<dyn Error>> {
+    let mut i = start;
+    let sign = if buf.bytes[i] == b'-' {i += 1; "-"} else {""};
+
+    let prefix = if buf.bytes[i] == b'0' && i + 1 < buf.len {buf.bytes[i + 1].to_ascii_lowercase()} else {0};
+    let radix = match prefix {
+        b'x' => 16,
+        b'o' => 8,
+        b'b' => 2,
+        _ => 10
+    };
+    let mut is_float = false;
+
+    if radix == 10 {
+        i = skip_digits(buf, i);
+
+        // A fractional part or exponent makes the number a float
+        if i + 1 < buf.len && buf.bytes[i] == b'.' && buf.bytes[i + 1].is_ascii_digit() {
+            i = skip_digits(buf, i + 1);
+            is_float = true;
+        }
+        if i < buf.len && (buf.bytes[i] == b'e' || buf.bytes[i] == b'E') {
+            let exp_sign = (i + 1 < buf.len && (buf.bytes[i + 1] == b'+' || buf.bytes[i + 1] == b'-')) as usize;
+            if i + 1 + exp_sign < buf.len && buf.bytes[i + 1 + exp_sign].is_ascii_digit() {
+                i = skip_digits(buf, i + 1 + exp_sign);
+                is_float = true;
+            }
+        }
+    }
+    else {i += 2;}
+
+    // Take every alphanumeric, including the `n` suffix that makes an integer a bigint, so trailing
+    // characters like the `g` in `0xfg` or the `abc` in `12abc` are reported rather than lexed as a name
+    while i < buf.len && (buf.bytes[i].is_ascii_alphanumeric() || buf.bytes[i] == b'_') {i += 1;}
+
+    buf.index = i;
+    let text = String::from_utf8(buf.bytes[start..i].to_vec())?;
+    let is_big = !is_float && text.ends_with('n');
+    let digits: String = text[sign.len() + if radix == 10 {0} else {2}..text.len() - is_big as usize]
+        .chars()
+        .filter(|&c| c != '_')
+        .collect();
+
+    if is_float {
+        return Ok(match format!("{}{}", sign, digits).parse::<f64>() {
+            Ok(f) if f.is_finite() => (Token::Float(f), i),
+            // Out of range floats parse as infinity rather than failing
+            Ok(_) => {error(buf, format!("Float literal `{}` is too large for a 64-bit float", text))?; (Token::Empty, i)},
+            Err(_) => {error(buf, format!("Invalid float literal: `{}`", text))?; (Token::Empty, i)}
+        });
+    }
+
+    if is_big {
+        return Ok(match BigInt::parse_bytes(format!("{}{}", sign, digits).as_bytes(), radix) {
+            Some(n) => (Token::BigNum(n), i),
+            None => {error(buf, format!("Invalid integer literal: `{}`", text))?; (Token::Empty, i)}
+        });
+    }
+
+    return Ok(match i64::from_str_radix(&format!("{}{}", sign, digits), radix) {
+        Ok(n) => (Token::Num(n), i),
+        Err(e) => {
+            let msg = match e.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => format!("Integer literal `{}` doesn't fit in 64 bits", text),
+                _ => format!("Invalid integer literal: `{}`", text)
+            };
+            error(buf, msg)?;
+
+            (Token::Empty, i)
+        }
+    });
+}
+
 pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
-    buf.last_index = buf.index;
     let mut i = buf.index;
-    let start: usize;
     let mut tok = Token::Empty;
     
     // Ignore leading whitespace
@@ -103,17 +183,18 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
     }
 
     if i == buf.len {
+        buf.tok_start = i;
         buf.index = i;
         return Ok(tok);
     }
 
     // Ignore comments
-    while buf.bytes[i] == b';' {
+    while i < buf.len && buf.bytes[i] == b';' {
         while i < buf.len {
-            i += 1;
             if buf.bytes[i] == b'\n' {
                 break;
             }
+            i += 1;
         }
         // Ignore whitespace after comment
         while i < buf.len {
@@ -125,18 +206,18 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
     }
 
     if i >= buf.len {
-        buf.index = i;
+        buf.tok_start = buf.len;
+        buf.index = buf.len;
         return Ok(tok);
     }
-    start = i;
-
-    if buf.bytes[i].is_ascii_digit() {
-        while i < buf.len {
-            if !buf.bytes[i].is_ascii_digit() {break;}
-            i += 1;
-        }
+    let start = i;
+    buf.tok_start = start;
 
-        tok = Token::Num(String::from_utf8(buf.bytes[start..i].to_vec())?);
+    // A `-` directly followed by a digit starts a negative number rather than being an operator
+    if buf.bytes[i].is_ascii_digit() || (buf.bytes[i] == b'-' && i + 1 < buf.len && buf.bytes[i + 1].is_ascii_digit()) {
+        let (num, end) = lex_number(buf, start)?;
+        tok = num;
+        i = end;
     }
     else if buf.bytes[i] == b'"' {
         let mut s = String::new();
@@ -193,6 +274,10 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
         i += 1;
         tok = Token::Colon;
     }
+    else if buf.bytes[i] == b'-' && i + 1 < buf.len && buf.bytes[i + 1] == b'>' {
+        i += 2;
+        tok = Token::Arrow;
+    }
     else if buf.bytes[i] == b'+' || buf.bytes[i] == b'-' || buf.bytes[i] == b'*' || buf.bytes[i] == b'/' 
         || buf.bytes[i] == b'=' || buf.bytes[i] == b'<' || buf.bytes[i] == b'>' || buf.bytes[i] == b'|'
         || buf.bytes[i] == b'&'
@@ -215,9 +300,12 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
     }
     else if buf.bytes[i].is_ascii_alphabetic() || buf.bytes[i] == b'_' {
         while i < buf.len {
-            if (buf.bytes[i].is_ascii_alphanumeric() || buf.bytes[i] == b'_') == false {break;}
+            // Dots separate the parts of qualified names like `vec.push`
+            if !(buf.bytes[i].is_ascii_alphanumeric() || buf.bytes[i] == b'_' || buf.bytes[i] == b'.') {break;}
             i += 1;
         }
+        // `let*` is the only name with a symbol in it
+        if &buf.bytes[start..i] == b"let" && i < buf.len && buf.bytes[i] == b'*' {i += 1;}
         tok = Token::Name(String::from_utf8(buf.bytes[start..i].to_vec())?);
     }
     else {
@@ -229,7 +317,9 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
         Token::Name(ref s) => {
             match s.as_str() {
                 "def" => Token::Def,
-                "let" => Token::Let,
+                "let" => Token::Let(LetKind::Parallel),
+                "let*" => Token::Let(LetKind::Sequential),
+                "letrec" => Token::Let(LetKind::Recursive),
                 "lambda" => Token::Lambda,
                 "if" => Token::If,
                 "else" => Token::Else,
@@ -237,12 +327,19 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
                 "false" => Token::False,
                 "none" => Token::None,
                 "use" => Token::Use,
+                "struct" => Token::Struct,
+                "enum" => Token::Enum,
+                "match" => Token::Match,
+                "do" => Token::Do,
                 "function" => Token::Typename(Type::Function),
                 "int" => Token::Typename(Type::Int),
+                "bigint" => Token::Typename(Type::BigInt),
+                "float" => Token::Typename(Type::Float),
                 "bool" => Token::Typename(Type::Bool),
                 "string" => Token::Typename(Type::Str),
                 "list" => Token::Typename(Type::List),
                 "none_t" => Token::Typename(Type::None),
+                "module" => Token::Typename(Type::Module),
                 _ => tok
             }
         },
@@ -253,61 +350,101 @@ pub fn get_tok(buf: &mut Buffer) -> Result<Token, Box<dyn Error>> {
     return Ok(tok);
 }
 
+/// Byte range of a piece of source code within a `Buffer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+    /// Creates a span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end
+        }
+    }
+}
+
+/// A file or snippet of source code that was added to a `Buffer`
+pub struct Source {
+    /// Path of the file, or `None` for code that didn't come from a file
+    pub name: Option<String>,
+    pub text: Vec<u8>
+}
+
+/// A run of bytes in a `Buffer` that came from one source
+struct Segment {
+    /// Offset of the run in the buffer
+    start: usize,
+    /// Index of the source in `Buffer::sources`
+    source: usize,
+    /// Offset of the run in the source's text
+    offset: usize
+}
+
 pub struct Buffer {
     pub bytes: Vec<u8>,
     pub index: usize,
-    pub last_index: usize,
-    pub len: usize
+    pub tok_start: usize,
+    pub len: usize,
+    pub sources: Vec<Source>,
+    /// Maps byte ranges of the buffer back to their sources, ordered by `start`
+    segments: Vec<Segment>
 }
 
 impl Buffer {
-    pub fn new(raw_buf: Vec<u8>) -> Result<Buffer, Box<dyn Error>> {
-        let bytes = String::from_utf8(raw_buf)?
-            .replace("\r", "")
-            .trim_end()
-            .as_bytes()
-            .to_vec();
-        let len = bytes.len();
-
-        return Ok(Buffer {
-            bytes,
-            index: 0,
-            last_index: 0,
-            len
-        })
-    }
-
     pub fn new_empty() -> Buffer {
         Buffer {
             bytes: Vec::new(),
             index: 0,
-            last_index: 0,
-            len: 0
+            tok_start: 0,
+            len: 0,
+            sources: Vec::new(),
+            segments: Vec::new()
+        }
+    }
+
+    /// Returns the span of the most recently lexed token
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.tok_start,
+            end: self.index
         }
     }
 
-    pub fn add_line(&mut self, stdin: &std::io::Stdin) -> Result<(), Box<dyn Error>> {
-        let mut io_buf = String::new();
-        stdin.read_line(&mut io_buf)?;
+    /// Finds the source that the byte at `pos` came from, along with its offset in that source
+    pub fn source_pos(&self, pos: usize) -> Option<(&Source, usize)> {
+        let i = self.segments.partition_point(|seg| seg.start <= pos).checked_sub(1)?;
+        let seg = &self.segments[i];
+        let source = &self.sources[seg.source];
 
-        let bytes = io_buf
+        // Separators between sources belong to the source before them
+        return Some((source, (seg.offset + pos - seg.start).min(source.text.len())));
+    }
+
+    /// Appends source code to the end of the buffer and moves to its start
+    pub fn append(&mut self, name: Option<&str>, src: &str) {
+        let bytes = src
+            .replace("\r", "")
             .trim_end()
             .as_bytes()
             .to_vec();
 
-        self.len += bytes.len() + 1;
-        self.bytes.push(b'\n');
-        self.bytes.extend(bytes);
-
-        return Ok(());
-    }
-
-    pub fn splice(&mut self, slice: &[u8]) {
-        self.bytes.push(b'\n');
-        self.index += 1;
-        self.len += 1;
+        if !self.bytes.is_empty() {self.bytes.push(b'\n');}
+        self.index = self.bytes.len();
+        self.segments.push(Segment {
+            start: self.index,
+            source: self.sources.len(),
+            offset: 0
+        });
+        self.bytes.extend(&bytes);
+        self.len = self.bytes.len();
 
-        self.bytes.splice(self.index..self.index, slice.iter().cloned());
-        self.len += slice.len();
+        self.sources.push(Source {
+            name: name.map(str::to_owned),
+            text: bytes
+        });
     }
 }
@@ -0,0 +1,303 @@
+pub mod chunk;
+pub mod compiler;
+
+use chunk::{
+    Instr,
+    Capture,
+    Captures,
+    Proto
+};
+use compiler::compile;
+
+use crate::{
+    tokenizer::{
+        Buffer,
+        Span
+    },
+    ast::Expr,
+    object::{
+        Type,
+        Object
+    },
+    error::ErrorKind,
+    pattern::{
+        select,
+        destructure
+    },
+    call::{
+        Func,
+        FuncKind,
+        call_function,
+        trace_call,
+        check_args,
+        check_arg_types,
+        check_return
+    },
+    parser::{
+        op::apply_op,
+        output
+    },
+    module::{
+        ModuleId,
+        Modules
+    }
+};
+
+use std::{
+    error::Error,
+    cell::RefCell,
+    mem::replace,
+    rc::Rc
+};
+
+/// Compiles and runs a top-level expression of a module
+pub fn run_top_level(buf: &mut Buffer, modules: &mut Modules, module: ModuleId, expr: &Expr) -> Result<Object, Box<dyn Error>> {
+    let proto = compile(module, expr)?;
+
+    return Vm::new().run(buf, modules, proto);
+}
+
+/// A function activation on the VM's call stack
+struct Frame {
+    proto: Rc<Proto>,
+    captures: Captures,
+    ip: usize,
+    /// Index of the frame's first slot in the value stack
+    base: usize,
+    /// The expression that called the function, unless it was called from Rust
+    call_site: Option<Span>
+}
+
+/// A stack-based virtual machine for running compiled Sputter code
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<Object>,
+    frames: Vec<Frame>
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm::default()
+    }
+
+    /// Runs a compiled top-level expression to completion
+    pub fn run(&mut self, buf: &mut Buffer, modules: &mut Modules, proto: Rc<Proto>) -> Result<Object, Box<dyn Error>> {
+        self.stack.clear();
+        self.frames.clear();
+
+        // Stands in for the callee beneath a frame, so the top level can return like any other function
+        self.stack.push(Object::None);
+
+        return self.execute(buf, modules, Frame {
+            proto,
+            captures: Rc::from(Vec::new()),
+            ip: 0,
+            base: 1,
+            call_site: None
+        });
+    }
+
+    /// Calls a compiled function with arguments that have already been checked
+    pub fn call(&mut self, buf: &mut Buffer, modules: &mut Modules, proto: Rc<Proto>, captures: Captures, args: Vec<Object>) -> Result<Object, Box<dyn Error>> {
+        self.stack.clear();
+        self.frames.clear();
+
+        self.stack.push(Object::None);
+        self.stack.extend(args);
+
+        return self.execute(buf, modules, Frame {
+            proto,
+            captures,
+            ip: 0,
+            base: 1,
+            call_site: None
+        });
+    }
+
+    /// Runs `frame` until it returns, adding the active calls to the trace of any error
+    fn execute(&mut self, buf: &mut Buffer, modules: &mut Modules, mut frame: Frame) -> Result<Object, Box<dyn Error>> {
+        return self.run_frame(buf, modules, &mut frame).map_err(|e| self.trace(buf, &frame, e));
+    }
+
+    /// Adds the active calls to the trace of `err`, innermost first
+    fn trace(&self, buf: &Buffer, frame: &Frame, mut err: Box<dyn Error>) -> Box<dyn Error> {
+        for f in std::iter::once(frame).chain(self.frames.iter().rev()) {
+            if let Some(span) = f.call_site {
+                let args = self.stack[f.base..f.base + f.proto.params.len()].to_vec();
+                err = trace_call(err, buf, &f.proto.name, span, args);
+            }
+        }
+
+        return err;
+    }
+
+    /// Runs instructions until `frame` returns
+    fn run_frame(&mut self, buf: &mut Buffer, modules: &mut Modules, frame: &mut Frame) -> Result<Object, Box<dyn Error>> {
+        loop {
+            let chunk = &frame.proto.chunk;
+            let instr = chunk.code[frame.ip];
+            let span = chunk.spans[frame.ip];
+            frame.ip += 1;
+
+            match instr {
+                Instr::Const(i) => self.stack.push(chunk.consts[i].clone()),
+                Instr::GetLocal(slot) => self.stack.push(self.stack[frame.base + slot].clone()),
+                Instr::GetCapture(i) => self.stack.push(frame.captures[i].borrow().clone()),
+                Instr::GetGlobal(i) => {
+                    let name = &chunk.names[i];
+                    let obj = match modules.get(frame.proto.module, name) {
+                        Some(obj) => obj.clone(),
+                        None => {output::error_at(buf, span, ErrorKind::Name, format!("Undefined name: `{}`", name))?; Object::None}
+                    };
+
+                    self.stack.push(obj);
+                },
+                Instr::GetMember(i) => {
+                    let obj = self.pop();
+                    self.stack.push(modules.member(buf, &obj, &chunk.names[i], span)?);
+                },
+                Instr::DefGlobal(i) => {
+                    let func = self.pop();
+                    modules.define(frame.proto.module, chunk.names[i].clone(), func);
+                    self.stack.push(Object::None);
+                },
+                Instr::MakeList(n) => {
+                    let items = self.stack.split_off(self.stack.len() - n);
+                    self.stack.push(Object::List(items.into()));
+                },
+                Instr::MakeMap(n) => {
+                    let items = self.stack.split_off(self.stack.len() - 2 * n);
+                    let map = items.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+                    self.stack.push(Object::Map(map));
+                },
+                Instr::MakeClosure(i) => {
+                    let proto = chunk.protos[i].clone();
+                    let captures = proto.captures.iter().map(|c| RefCell::new(match c {
+                        // Functions in a `letrec` can capture ones after them, which `Fix` fills in
+                        Capture::Local(slot) => self.stack.get(frame.base + slot).cloned().unwrap_or(Object::None),
+                        Capture::Outer(i) => frame.captures[*i].borrow().clone()
+                    })).collect();
+
+                    self.stack.push(Object::Function(Func {
+                        name: proto.name.clone(),
+                        params: proto.params.clone(),
+                        ret_type: proto.ret_type.clone(),
+                        kind: FuncKind::Compiled(proto, captures)
+                    }));
+                },
+                Instr::Op(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+
+                    self.stack.push(apply_op(buf, span, op, lhs, rhs)?);
+                },
+                Instr::Jump(target) => frame.ip = target,
+                Instr::JumpIfFalse(target) => {
+                    match self.pop() {
+                        Object::Bool(true) => (),
+                        Object::Bool(false) => frame.ip = target,
+                        t => output::error_at(buf, span, ErrorKind::Type, format!("Conditional expression must have type `bool`, not `{:?}`", t))?
+                    }
+                },
+                Instr::Match(i) => {
+                    let obj = self.pop();
+                    let table = &chunk.matches[i];
+                    let (arm, bound) = select(buf, table.patterns.iter(), &obj, span)?;
+
+                    self.stack.extend(bound);
+                    frame.ip = table.targets[arm];
+                },
+                Instr::Destructure(i) => {
+                    let obj = self.pop();
+                    let bound = destructure(buf, &chunk.patterns[i], &obj, span)?;
+                    self.stack.extend(bound);
+                },
+                Instr::Call(i) | Instr::TailCall(i) => {
+                    let call = &chunk.calls[i];
+                    let callee = self.stack.len() - call.argc() - 1;
+                    let func = match &self.stack[callee] {
+                        Object::Function(f) => f,
+                        // A parenthesized value with no arguments evaluates to itself
+                        _ if call.argc() == 0 => continue,
+                        obj => {output::error_at(buf, span, ErrorKind::Type, format!("`{:?}` is not a function", obj))?; continue}
+                    };
+
+                    check_arg_types(buf, func, &self.stack[callee + 1..], &call.names, &call.arg_spans)?;
+                    let arranged = check_args(buf, func, &self.stack[callee + 1..], &call.names, span)?;
+                    // A tail call would skip checking the result against this function's return type
+                    let ret_type = &frame.proto.ret_type;
+                    let tail = matches!(instr, Instr::TailCall(_)) && (*ret_type == Type::Any || *ret_type == func.ret_type);
+
+                    if let FuncKind::Compiled(proto, captures) = &func.kind {
+                        let mut callee_frame = Frame {
+                            proto: proto.clone(),
+                            captures: captures.clone(),
+                            ip: 0,
+                            base: callee + 1,
+                            call_site: Some(span)
+                        };
+                        if let Some(args) = arranged {
+                            self.stack.truncate(callee + 1);
+                            self.stack.extend(args);
+                        }
+
+                        if tail {
+                            // Slide the callee and its arguments down over the current frame
+                            self.stack.drain(frame.base - 1..callee);
+                            callee_frame.base = frame.base;
+                            *frame = callee_frame;
+                        }
+                        else {self.frames.push(replace(frame, callee_frame));}
+                    }
+                    else {
+                        let func = func.clone();
+                        let args: Vec<Object> = self.stack.drain(callee + 1..).collect();
+                        self.stack.pop();
+
+                        let res = call_function(buf, modules, &func, arranged.unwrap_or(args), span)?;
+                        self.stack.push(res);
+                    }
+                },
+                Instr::Return => {
+                    let res = self.pop();
+                    check_return(buf, &frame.proto.name, &frame.proto.ret_type, &res, span)?;
+                    // Remove the frame along with the callee beneath it
+                    self.stack.truncate(frame.base - 1);
+
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            self.stack.push(res);
+                            *frame = caller;
+                        },
+                        None => return Ok(res)
+                    }
+                },
+                Instr::EndScope(n) => {
+                    let res = self.pop();
+                    self.stack.truncate(self.stack.len() - n);
+                    self.stack.push(res);
+                },
+                Instr::Fix(n) => {
+                    let first = self.stack.len() - n;
+                    for closure in &self.stack[first..] {
+                        if let Object::Function(Func {kind: FuncKind::Compiled(proto, caps), ..}) = closure {
+                            for (cap, src) in caps.iter().zip(proto.captures.iter()) {
+                                if let Capture::Local(slot) = src {
+                                    if frame.base + slot >= first {*cap.borrow_mut() = self.stack[frame.base + slot].clone();}
+                                }
+                            }
+                        }
+                    }
+                },
+                Instr::Use(i) => {
+                    modules.use_module(buf, frame.proto.module, &chunk.uses[i], span, run_top_level)?;
+                    self.stack.push(Object::None);
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap()
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    tokenizer::Span,
+    ast::{
+        UseDecl,
+        Pattern
+    },
+    object::{
+        Type,
+        Object
+    },
+    module::ModuleId,
+    call::Param,
+    parser::op::Op
+};
+
+use std::{
+    cell::RefCell,
+    rc::Rc
+};
+
+/// Values captured by a compiled closure. They only change when `letrec` ties
+/// closures that capture each other together.
+pub type Captures = Rc<[RefCell<Object>]>;
+
+/// A single VM instruction
+#[derive(Clone, Copy, Debug)]
+pub enum Instr {
+    /// Push `consts[i]`
+    Const(usize),
+    /// Push the value in slot `i` of the current frame
+    GetLocal(usize),
+    /// Push captured value `i` of the running closure
+    GetCapture(usize),
+    /// Push the global named `names[i]`
+    GetGlobal(usize),
+    /// Pop a module and push its member named `names[i]`
+    GetMember(usize),
+    /// Pop a value and bind it to the global named `names[i]`, then push `none`
+    DefGlobal(usize),
+    /// Pop `n` values and push them as a list
+    MakeList(usize),
+    /// Pop `n` key-value pairs and push them as a map
+    MakeMap(usize),
+    /// Push a closure over `protos[i]`, capturing values from the current frame
+    MakeClosure(usize),
+    /// Pop two operands and push the result of an operator
+    Op(Op),
+    Jump(usize),
+    /// Pop a condition and jump if it's `false`
+    JumpIfFalse(usize),
+    /// Pop a value and jump to the arm of `matches[i]` it matches, pushing the values its pattern binds
+    Match(usize),
+    /// Pop a value and push the values bound by matching it against `patterns[i]`
+    Destructure(usize),
+    /// Call the value beneath the arguments of `calls[i]`
+    Call(usize),
+    /// Call the value beneath the arguments of `calls[i]`, replacing the current frame
+    TailCall(usize),
+    /// Pop the result of the current frame and return it to the caller
+    Return,
+    /// Drop `n` values beneath the top of the stack
+    EndScope(usize),
+    /// Point the captures of the top `n` closures, made by a `letrec`, at each other
+    Fix(usize),
+    /// Load the module for `uses[i]` and bind what it imports, then push `none`
+    Use(usize)
+}
+
+/// Where a closure gets a captured value from when it's created
+#[derive(Clone, Copy, Debug)]
+pub enum Capture {
+    /// A slot in the frame creating the closure
+    Local(usize),
+    /// A value captured by the closure creating the closure
+    Outer(usize)
+}
+
+/// The patterns of a `match` expression along with where the code for each arm starts
+pub struct MatchTable {
+    pub patterns: Vec<Pattern>,
+    pub targets: Vec<usize>
+}
+
+/// The arguments of a call. Keyword arguments are pushed after the positional ones.
+pub struct CallSite {
+    /// Source location of each argument, where type errors in it are reported
+    pub arg_spans: Vec<Span>,
+    /// Names of the keyword arguments
+    pub names: Vec<String>
+}
+
+impl CallSite {
+    /// Number of values pushed for the arguments
+    pub fn argc(&self) -> usize {
+        self.arg_spans.len()
+    }
+}
+
+/// Compiled code along with the data it refers to
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Instr>,
+    /// Source location of each instruction in `code`
+    pub spans: Vec<Span>,
+    pub consts: Vec<Object>,
+    pub names: Vec<String>,
+    pub protos: Vec<Rc<Proto>>,
+    pub uses: Vec<UseDecl>,
+    pub matches: Vec<MatchTable>,
+    pub patterns: Vec<Pattern>,
+    pub calls: Vec<CallSite>
+}
+
+impl Chunk {
+    /// Appends an instruction, returning its index
+    pub fn emit(&mut self, instr: Instr, span: Span) -> usize {
+        self.code.push(instr);
+        self.spans.push(span);
+
+        return self.code.len() - 1;
+    }
+
+    /// Points the jump at `idx` to the next instruction to be emitted
+    pub fn patch_jump(&mut self, idx: usize) {
+        let target = self.code.len();
+
+        self.code[idx] = match self.code[idx] {
+            Instr::Jump(_) => Instr::Jump(target),
+            Instr::JumpIfFalse(_) => Instr::JumpIfFalse(target),
+            instr => instr
+        };
+    }
+
+    pub fn add_const(&mut self, obj: Object) -> usize {
+        self.consts.push(obj);
+
+        return self.consts.len() - 1;
+    }
+
+    pub fn add_name(&mut self, name: &str) -> usize {
+        if let Some(i) = self.names.iter().position(|n| n == name) {
+            return i;
+        }
+        self.names.push(name.to_owned());
+
+        return self.names.len() - 1;
+    }
+}
+
+/// A compiled function, shared by every closure created from it
+pub struct Proto {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret_type: Type,
+    pub captures: Vec<Capture>,
+    /// Module whose globals the function sees
+    pub module: ModuleId,
+    pub chunk: Chunk
+}
@@ -0,0 +1,374 @@
+use super::chunk::{
+    Instr,
+    Capture,
+    Chunk,
+    Proto,
+    MatchTable,
+    CallSite
+};
+
+use crate::{
+    tokenizer::Span,
+    ast::{
+        Expr,
+        ExprKind,
+        Binding,
+        LetKind,
+        MatchArm,
+        Pattern
+    },
+    object::{
+        Type,
+        Object
+    },
+    module::ModuleId,
+    call::Param
+};
+
+use std::{
+    error::Error,
+    rc::Rc
+};
+
+struct Local {
+    name: String,
+    slot: usize
+}
+
+/// How a name resolves inside the function being compiled
+enum Var {
+    Local(usize),
+    Capture(usize)
+}
+
+/// State for a single function while its body is being compiled
+struct FnState {
+    name: String,
+    params: Vec<Param>,
+    ret_type: Type,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    captures: Vec<(String, Capture)>,
+    /// Number of values the frame will have on the stack at the current instruction
+    depth: usize
+}
+
+pub struct Compiler {
+    module: ModuleId,
+    fns: Vec<FnState>
+}
+
+/// Compiles a top-level expression of `module` into a function that takes no arguments
+pub fn compile(module: ModuleId, expr: &Expr) -> Result<Rc<Proto>, Box<dyn Error>> {
+    let mut compiler = Compiler {
+        module,
+        fns: Vec::new()
+    };
+
+    compiler.begin_fn("<top level>".to_owned(), Vec::new(), Type::Any);
+    compiler.expr(expr, true)?;
+    compiler.emit(Instr::Return, expr.span);
+
+    return Ok(Rc::new(compiler.end_fn()));
+}
+
+impl Compiler {
+    fn current(&mut self) -> &mut FnState {
+        self.fns.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instr: Instr, span: Span) -> usize {
+        let state = self.current();
+
+        state.depth = match instr {
+            Instr::Const(_) | Instr::GetLocal(_) | Instr::GetCapture(_) | Instr::GetGlobal(_)
+                | Instr::MakeClosure(_) | Instr::Use(_) => state.depth + 1,
+            Instr::GetMember(_) | Instr::DefGlobal(_) | Instr::Jump(_) | Instr::Fix(_) => state.depth,
+            Instr::MakeList(n) => state.depth + 1 - n,
+            Instr::MakeMap(n) => state.depth + 1 - 2 * n,
+            Instr::Op(_) | Instr::JumpIfFalse(_) | Instr::Match(_) | Instr::Destructure(_)
+                | Instr::Return => state.depth - 1,
+            Instr::EndScope(n) => state.depth - n,
+            Instr::Call(i) | Instr::TailCall(i) => state.depth - state.chunk.calls[i].argc()
+        };
+
+        return state.chunk.emit(instr, span);
+    }
+
+    fn begin_fn(&mut self, name: String, params: Vec<Param>, ret_type: Type) {
+        let locals = params.iter().enumerate().map(|(slot, p)| Local {
+            name: p.name.clone(),
+            slot
+        }).collect();
+
+        self.fns.push(FnState {
+            name,
+            depth: params.len(),
+            params,
+            ret_type,
+            chunk: Chunk::default(),
+            locals,
+            captures: Vec::new()
+        });
+    }
+
+    fn end_fn(&mut self) -> Proto {
+        let state = self.fns.pop().unwrap();
+
+        return Proto {
+            name: state.name,
+            params: state.params,
+            ret_type: state.ret_type,
+            captures: state.captures.into_iter().map(|(_, c)| c).collect(),
+            module: self.module,
+            chunk: state.chunk
+        };
+    }
+
+    /// Resolves `name` in the function at `level`, capturing it from enclosing functions if needed
+    fn resolve(&mut self, level: usize, name: &str) -> Option<Var> {
+        let state = &self.fns[level];
+
+        if let Some(local) = state.locals.iter().rev().find(|l| l.name == name) {
+            return Some(Var::Local(local.slot));
+        }
+        if let Some(i) = state.captures.iter().position(|(n, _)| n == name) {
+            return Some(Var::Capture(i));
+        }
+        if level == 0 {return None;}
+
+        let capture = match self.resolve(level - 1, name)? {
+            Var::Local(slot) => Capture::Local(slot),
+            Var::Capture(i) => Capture::Outer(i)
+        };
+        let captures = &mut self.fns[level].captures;
+        captures.push((name.to_owned(), capture));
+
+        return Some(Var::Capture(captures.len() - 1));
+    }
+
+    /// Compiles an expression, using tail calls if `tail` is set and the
+    /// expression's value is returned directly from the current function
+    fn expr(&mut self, expr: &Expr, tail: bool) -> Result<(), Box<dyn Error>> {
+        match &expr.kind {
+            ExprKind::Int(i) => self.constant(Object::Int(*i), expr.span),
+            ExprKind::Float(f) => self.constant(Object::Float(*f), expr.span),
+            ExprKind::BigInt(n) => self.constant(Object::BigInt(n.clone()), expr.span),
+            ExprKind::Str(s) => self.constant(Object::Str(s.clone()), expr.span),
+            ExprKind::Bool(b) => self.constant(Object::Bool(*b), expr.span),
+            ExprKind::None => self.constant(Object::None, expr.span),
+            ExprKind::Name(n) => {
+                let level = self.fns.len() - 1;
+                let instr = match self.resolve(level, n) {
+                    Some(Var::Local(slot)) => Instr::GetLocal(slot),
+                    Some(Var::Capture(i)) => Instr::GetCapture(i),
+                    None => Instr::GetGlobal(self.current().chunk.add_name(n))
+                };
+
+                self.emit(instr, expr.span);
+            },
+            ExprKind::Member(module, member) => {
+                self.expr(module, false)?;
+                let idx = self.current().chunk.add_name(member);
+                self.emit(Instr::GetMember(idx), expr.span);
+            },
+            ExprKind::List(items) => {
+                for item in items {self.expr(item, false)?;}
+                self.emit(Instr::MakeList(items.len()), expr.span);
+            },
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key, false)?;
+                    self.expr(value, false)?;
+                }
+                self.emit(Instr::MakeMap(entries.len()), expr.span);
+            },
+            ExprKind::Call(head, args, keywords) => {
+                self.expr(head, false)?;
+                for arg in args {self.expr(arg, false)?;}
+                for (_, arg) in keywords {self.expr(arg, false)?;}
+
+                let chunk = &mut self.current().chunk;
+                chunk.calls.push(CallSite {
+                    arg_spans: args.iter().chain(keywords.iter().map(|(_, arg)| arg)).map(|arg| arg.span).collect(),
+                    names: keywords.iter().map(|(name, _)| name.clone()).collect()
+                });
+                let idx = chunk.calls.len() - 1;
+
+                self.emit(if tail {Instr::TailCall(idx)} else {Instr::Call(idx)}, expr.span);
+            },
+            ExprKind::Op(op, lhs, rhs) => {
+                self.expr(lhs, false)?;
+                self.expr(rhs, false)?;
+                self.emit(Instr::Op(*op), expr.span);
+            },
+            ExprKind::If(cond, then, otherwise) => {
+                self.expr(cond, false)?;
+                let skip_then = self.emit(Instr::JumpIfFalse(0), cond.span);
+
+                self.expr(then, tail)?;
+                let skip_else = self.emit(Instr::Jump(0), expr.span);
+                self.current().depth -= 1;
+
+                self.current().chunk.patch_jump(skip_then);
+                self.expr(otherwise, tail)?;
+                self.current().chunk.patch_jump(skip_else);
+            },
+            ExprKind::Do(exprs) => {
+                let (last, init) = exprs.split_last().unwrap();
+                for e in init {self.expr(e, false)?;}
+
+                self.expr(last, tail)?;
+                if !init.is_empty() {self.emit(Instr::EndScope(init.len()), expr.span);}
+            },
+            ExprKind::Match(value, arms) => self.match_expr(value, arms, tail, expr.span)?,
+            ExprKind::Let(kind, bindings, body) => self.let_expr(*kind, bindings, body, tail)?,
+            ExprKind::Def(name, params, ret_type, body) => {
+                self.function(name.clone(), params, ret_type, body, expr.span)?;
+                let idx = self.current().chunk.add_name(name);
+                self.emit(Instr::DefGlobal(idx), expr.span);
+            },
+            ExprKind::Lambda(params, ret_type, body) => self.function("lambda".to_owned(), params, ret_type, body, expr.span)?,
+            ExprKind::Struct(def) => {
+                self.constant(def.value(), expr.span);
+                let idx = self.current().chunk.add_name(&def.ty.name);
+                self.emit(Instr::DefGlobal(idx), expr.span);
+            },
+            ExprKind::Enum(variants) => {
+                for def in variants {
+                    self.constant(def.value(), expr.span);
+                    let idx = self.current().chunk.add_name(&def.ty.name);
+                    self.emit(Instr::DefGlobal(idx), expr.span);
+                }
+
+                // Leave a single `none` behind, like every other definition
+                match variants.len() {
+                    0 => self.constant(Object::None, expr.span),
+                    n => {self.emit(Instr::EndScope(n - 1), expr.span);}
+                }
+            },
+            ExprKind::Use(decl) => {
+                let chunk = &mut self.current().chunk;
+                chunk.uses.push(decl.clone());
+                let idx = chunk.uses.len() - 1;
+                self.emit(Instr::Use(idx), expr.span);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn constant(&mut self, obj: Object, span: Span) {
+        let idx = self.current().chunk.add_const(obj);
+        self.emit(Instr::Const(idx), span);
+    }
+
+    fn let_expr(&mut self, kind: LetKind, bindings: &[Binding], body: &Expr, tail: bool) -> Result<(), Box<dyn Error>> {
+        // Names in a `letrec` are visible to every value, so they're declared before the values exist
+        if kind == LetKind::Recursive {
+            let names: Vec<&str> = bindings.iter().flat_map(|b| b.pattern.names()).collect();
+            let first = self.current().depth;
+            self.declare_locals(&names, first);
+        }
+
+        let mut count = 0;
+        let mut pending = Vec::new();
+        for binding in bindings {
+            self.expr(&binding.val, false)?;
+
+            // A plain name binds the value where it is, while a pattern replaces it with the values it binds
+            let names = binding.pattern.names();
+            if !matches!(binding.pattern, Pattern::Bind(_)) {
+                let chunk = &mut self.current().chunk;
+                chunk.patterns.push(binding.pattern.clone());
+                let idx = chunk.patterns.len() - 1;
+                self.emit(Instr::Destructure(idx), binding.span);
+                self.current().depth += names.len();
+            }
+
+            let first = self.current().depth - names.len();
+            match kind {
+                LetKind::Sequential => self.declare_locals(&names, first),
+                LetKind::Parallel => pending.push((names.clone(), first)),
+                LetKind::Recursive => ()
+            }
+            count += names.len();
+        }
+
+        // Values in a plain `let` can't see each other's names
+        for (names, first) in pending {self.declare_locals(&names, first);}
+        if kind == LetKind::Recursive {self.emit(Instr::Fix(count), body.span);}
+
+        self.expr(body, tail)?;
+        self.emit(Instr::EndScope(count), body.span);
+
+        let state = self.current();
+        state.locals.truncate(state.locals.len() - count);
+
+        return Ok(());
+    }
+
+    /// Makes the stack slots starting at `first` locals with the given names,
+    /// shadowing any outer names
+    fn declare_locals(&mut self, names: &[&str], first: usize) {
+        for (slot, name) in (first..).zip(names) {
+            self.current().locals.push(Local {
+                name: (*name).to_owned(),
+                slot
+            });
+        }
+    }
+
+    fn match_expr(&mut self, value: &Expr, arms: &[MatchArm], tail: bool, span: Span) -> Result<(), Box<dyn Error>> {
+        self.expr(value, false)?;
+
+        let chunk = &mut self.current().chunk;
+        chunk.matches.push(MatchTable {
+            patterns: arms.iter().map(|arm| arm.pattern.clone()).collect(),
+            targets: Vec::new()
+        });
+        let table = chunk.matches.len() - 1;
+        self.emit(Instr::Match(table), span);
+
+        let depth = self.current().depth;
+        let mut ends = Vec::new();
+        for (i, arm) in arms.iter().enumerate() {
+            let state = self.current();
+            let target = state.chunk.code.len();
+            state.chunk.matches[table].targets.push(target);
+
+            let names = arm.pattern.names();
+            self.current().depth = depth + names.len();
+            self.declare_locals(&names, depth);
+
+            self.expr(&arm.body, tail)?;
+            self.emit(Instr::EndScope(names.len()), arm.body.span);
+
+            let state = self.current();
+            state.locals.truncate(state.locals.len() - names.len());
+            if i + 1 < arms.len() {
+                ends.push(self.emit(Instr::Jump(0), span));
+                self.current().depth = depth;
+            }
+        }
+
+        for end in ends {self.current().chunk.patch_jump(end);}
+
+        return Ok(());
+    }
+
+    fn function(&mut self, name: String, params: &[Param], ret_type: &Type, body: &Expr, span: Span) -> Result<(), Box<dyn Error>> {
+        self.begin_fn(name, params.to_vec(), ret_type.clone());
+        self.expr(body, true)?;
+        self.emit(Instr::Return, body.span);
+
+        let proto = self.end_fn();
+        let chunk = &mut self.current().chunk;
+        chunk.protos.push(Rc::new(proto));
+        let idx = chunk.protos.len() - 1;
+
+        self.emit(Instr::MakeClosure(idx), span);
+
+        return Ok(());
+    }
+}
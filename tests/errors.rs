@@ -0,0 +1,263 @@
+use sputter::{
+    Backend,
+    Interpreter,
+    Object,
+    ErrorKind,
+    SputterError,
+    error::TRACE_LIMIT
+};
+
+use std::{
+    cell::Cell,
+    error::Error,
+    rc::Rc
+};
+
+const BACKENDS: [Backend; 2] = [Backend::Tree, Backend::Vm];
+
+/// Unwraps the `SputterError` an evaluation is expected to raise
+fn expect_err(res: Result<Object, Box<dyn Error>>) -> SputterError {
+    match res {
+        Ok(obj) => panic!("expected an error, got `{:?}`", obj),
+        Err(e) => *e.downcast::<SputterError>().expect("expected a `SputterError`")
+    }
+}
+
+/// Evaluates `src`, expecting it to raise a `SputterError`
+fn eval_err(interp: &mut Interpreter, src: &str) -> SputterError {
+    expect_err(interp.eval_str(src))
+}
+
+#[test]
+fn arguments_are_evaluated_before_being_checked() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+        let touched = Rc::new(Cell::new(0));
+        let counter = touched.clone();
+        interp.register("touch", Vec::new(), move |_| {
+            counter.set(counter.get() + 1);
+            Ok(Object::None)
+        });
+
+        interp.eval_str("(def (f x: int y) x)\n(def (g a b: int = 1) a)").unwrap();
+
+        let err = eval_err(&mut interp, "\n(f \"a\" (touch))");
+        assert_eq!(err.kind, ErrorKind::Type, "{:?}", backend);
+        let loc = err.location.unwrap();
+        assert_eq!((loc.line, loc.col), (2, 4), "{:?}", backend);
+
+        let err = eval_err(&mut interp, "\n(g (touch) :b \"x\")");
+        let loc = err.location.unwrap();
+        assert_eq!((loc.line, loc.col), (2, 15), "{:?}", backend);
+
+        assert_eq!(touched.get(), 2, "{:?}", backend);
+    }
+}
+
+#[test]
+fn unknown_type_names_are_reported() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+
+        let err = eval_err(&mut interp, "(def (f x: strng) x)");
+        assert_eq!(err.kind, ErrorKind::Name);
+        assert_eq!(err.message, "Undefined type: `strng`");
+        assert_eq!(err.location.unwrap().col, 12);
+
+        let err = eval_err(&mut interp, "(enum Shape (Circle r: int))\n(def (f x: Circle) x)");
+        assert_eq!(err.kind, ErrorKind::Type);
+        assert_eq!(err.location.unwrap().line, 2);
+    }
+}
+
+#[test]
+fn records_are_told_apart_by_their_definition() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+        interp.add_search_path("tests/lib");
+        interp.eval_str("(use geo)\n(struct Point x: int)\n(def (norm p: geo.Point) (+ p.x p.y))").unwrap();
+
+        let err = eval_err(&mut interp, "(norm (Point 1))");
+        assert_eq!(err.kind, ErrorKind::Type, "{:?}", backend);
+
+        let res = interp.eval_str("(match (Point 1) ((geo.Point x y) x) (_ \"local\"))").unwrap();
+        assert_eq!(res, Object::from("local"));
+    }
+}
+
+#[test]
+fn errors_in_used_files_are_located_in_them() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+
+        let err = expect_err(interp.eval_file("tests/lib/uses_faulty.sp"));
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.message, "Cannot divide by 0");
+
+        let loc = err.location.unwrap();
+        assert_eq!(loc.file.as_deref(), Some("tests/lib/faulty.sp"), "{:?}", backend);
+        assert_eq!((loc.line, loc.col), (3, 5), "{:?}", backend);
+        assert_eq!(loc.source_line, "    (/ a b))");
+
+        let err = expect_err(interp.eval_file("tests/lib/missing.sp"));
+        assert_eq!(err.kind, ErrorKind::Io);
+        assert_eq!(err.location, None);
+    }
+}
+
+#[test]
+fn traces_list_active_calls_innermost_first() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+        interp.add_search_path("tests/lib");
+
+        let err = eval_err(&mut interp, "(use faulty)\n(faulty.divide 4 0)");
+        assert_eq!(err.trace.len(), 1, "{:?}", backend);
+        assert_eq!(err.hidden_frames, 0);
+
+        let frame = &err.trace[0];
+        assert_eq!(frame.name, "divide");
+        assert_eq!(frame.args, vec![Object::Int(4), Object::Int(0)]);
+        assert_eq!(frame.location.file, None);
+        assert_eq!((frame.location.line, frame.location.col), (2, 1));
+    }
+}
+
+#[test]
+fn deep_traces_are_truncated() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+
+        // `divide` replaces `(countdown 0)` with a tail call, leaving 21 active calls
+        let err = expect_err(interp.eval_file("tests/lib/uses_faulty.sp"));
+        assert_eq!(err.trace.len(), TRACE_LIMIT, "{:?}", backend);
+        assert_eq!(err.hidden_frames, 21 - TRACE_LIMIT, "{:?}", backend);
+
+        let divide = &err.trace[0];
+        assert_eq!(divide.name, "divide");
+        assert_eq!(divide.args, vec![Object::Int(1), Object::Int(0)]);
+        assert_eq!(divide.location.file.as_deref(), Some("tests/lib/faulty.sp"));
+        assert_eq!((divide.location.line, divide.location.col), (6, 17));
+
+        for (i, frame) in err.trace.iter().enumerate().skip(1) {
+            assert_eq!(frame.name, "countdown");
+            assert_eq!(frame.args, vec![Object::Int(i as i64)]);
+            assert_eq!((frame.location.line, frame.location.col), (6, 40));
+        }
+    }
+}
+
+#[test]
+fn interpreter_recovers_from_errors() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+
+        let err = eval_err(&mut interp, "(def (double n: int) (* n 2))\n(double nope)\n(def (never) 1)");
+        assert_eq!(err.kind, ErrorKind::Name);
+
+        // Definitions before the error are kept, and the rest of the source is skipped
+        assert!(interp.get("double").is_some());
+        assert!(interp.get("never").is_none());
+        assert_eq!(interp.eval_str("(double 21)").unwrap(), Object::Int(42));
+    }
+}
+
+#[test]
+fn exit_stops_evaluation_with_its_code() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+
+        let err = eval_err(&mut interp, "(def (quit) (exit 3))\n(quit)\n(def (after) 1)");
+        assert_eq!(err.kind, ErrorKind::Exit(3), "{:?}", backend);
+        assert!(interp.get("after").is_none());
+
+        let err = eval_err(&mut interp, "(exit 99999999999)");
+        assert_eq!(err.kind, ErrorKind::Runtime, "{:?}", backend);
+        assert_eq!(err.message, "Exit code 99999999999 is out of range for a 32-bit integer");
+    }
+}
+
+#[test]
+fn number_literals_with_trailing_letters_are_rejected() {
+    let mut interp = Interpreter::new();
+
+    for (src, msg) in &[("1.5n", "Invalid float literal: `1.5n`"), ("12abc", "Invalid integer literal: `12abc`")] {
+        let err = eval_err(&mut interp, src);
+        assert_eq!(err.kind, ErrorKind::Syntax);
+        assert_eq!(&err.message, msg);
+    }
+}
+
+#[test]
+fn float_literals_that_overflow_are_rejected() {
+    let mut interp = Interpreter::new();
+
+    for src in &["1e400", "-1.5e309"] {
+        let err = eval_err(&mut interp, src);
+        assert_eq!(err.kind, ErrorKind::Syntax);
+        assert_eq!(err.message, format!("Float literal `{}` is too large for a 64-bit float", src));
+    }
+    assert_eq!(interp.eval_str("1.5e308").unwrap(), Object::Float(1.5e308));
+}
+
+#[test]
+fn import_cycles_through_the_entry_file_are_reported() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+        let runs = Rc::new(Cell::new(0));
+        let counter = runs.clone();
+        interp.register("count_run", Vec::new(), move |_| {
+            counter.set(counter.get() + 1);
+            Ok(Object::None)
+        });
+
+        let err = expect_err(interp.eval_file("tests/lib/cycle_a.sp"));
+        assert_eq!(err.kind, ErrorKind::Import);
+        assert_eq!(err.message, "Import cycle: cycle_a -> cycle_b -> cycle_a", "{:?}", backend);
+        assert_eq!(err.location.unwrap().file.as_deref(), Some("tests/lib/cycle_b.sp"));
+
+        // The entry file isn't loaded again as a module of its own
+        assert_eq!(runs.get(), 1, "{:?}", backend);
+    }
+}
+
+#[test]
+fn matches_on_structs_must_handle_every_value() {
+    for &backend in &BACKENDS {
+        let mut interp = Interpreter::with_backend(backend);
+        interp.eval_str("(struct P x: int)").unwrap();
+
+        let err = eval_err(&mut interp, "(match (P 1) ((P 0) 1))");
+        assert_eq!(err.kind, ErrorKind::Syntax, "{:?}", backend);
+        assert_eq!(err.message, "`match` doesn't handle every value of struct `P`, add a `_` case");
+
+        assert_eq!(interp.eval_str("(match (P 1) ((P 0) 0) ((P x) x))").unwrap(), Object::Int(1));
+        assert_eq!(interp.eval_str("(match (P 1) ((P 0) 0) (_ 2))").unwrap(), Object::Int(2));
+    }
+}
+
+#[test]
+fn parameters_cannot_bind_a_name_twice() {
+    let mut interp = Interpreter::new();
+
+    for src in &["(def (f [a] [a]) a)", "(def (f a [a]) a)", "(lambda (a & a) a)"] {
+        let err = eval_err(&mut interp, src);
+        assert_eq!(err.kind, ErrorKind::Name, "{}", src);
+        assert_eq!(err.message, "Name `a` is bound more than once in parameters");
+    }
+}
+
+#[test]
+fn values_in_messages_are_shown_as_source() {
+    let mut interp = Interpreter::new();
+
+    let err = eval_err(&mut interp, "(let (([a b] [1 2 3])) a)");
+    assert_eq!(err.message, "`[1 2 3]` doesn't match the pattern `[a b]`");
+
+    // Variant patterns are only checked against values of their enum
+    let err = eval_err(&mut interp, "(enum E A B)\n(match \"b\" (A 1) (B 2))");
+    assert_eq!(err.message, "No pattern in `match` matches `\"b\"`");
+
+    let err = eval_err(&mut interp, "(get {\"a\" 1} \"b\")");
+    assert_eq!(err.message, "Key `\"b\"` isn't in the map");
+}
@@ -0,0 +1,55 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::Command,
+    thread
+};
+
+/// Scripts that aren't checked: `test.sp` is a benchmark that takes minutes to run
+const SKIPPED: [&str; 1] = ["test"];
+
+const BACKENDS: [&str; 2] = ["tree", "vm"];
+
+/// Runs a script on `backend`, returning a description of how its output differs
+/// from `tests/expected/<name>.out`
+fn check_script(path: PathBuf, backend: &str) -> Option<String> {
+    let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let expected = fs::read_to_string(format!("tests/expected/{}.out", name))
+        .unwrap_or_else(|_| panic!("no expected output for `{}`", path.display()));
+
+    let out = Command::new(env!("CARGO_BIN_EXE_sputter"))
+        .args(["-o", "-b", backend, "-I", "tests/lib"])
+        .arg(&path)
+        .output()
+        .expect("couldn't run the interpreter");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    if !out.status.success() {
+        Some(format!("{} ({}) failed:\n{}", name, backend, String::from_utf8_lossy(&out.stderr)))
+    } else if stdout != expected {
+        Some(format!("{} ({}) printed:\n{}\nexpected:\n{}", name, backend, stdout, expected))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn scripts_print_the_same_on_every_backend() {
+    let mut scripts: Vec<PathBuf> = fs::read_dir("tests").unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sp"))
+        .filter(|path| !SKIPPED.iter().any(|s| path.file_stem().unwrap() == *s))
+        .collect();
+    scripts.sort();
+
+    // Some scripts run long loops, so they're run side by side
+    let runs: Vec<_> = scripts.iter()
+        .flat_map(|path| BACKENDS.iter().map(move |&backend| {
+            let path = path.clone();
+            thread::spawn(move || check_script(path, backend))
+        }))
+        .collect();
+
+    let failures: Vec<String> = runs.into_iter().filter_map(|run| run.join().unwrap()).collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}